@@ -11,11 +11,17 @@ use structopt::StructOpt;
 use lazy_static::lazy_static;
 use regex::Regex;
 
-use crate::commands::roi::Frequency;
-use crate::commands::{accounts, balance, commodities, payees, prices, register, roi, statistics};
+use crate::commands::balance::ExtraColumns;
+use crate::commands::loan::Loan;
+use crate::commands::roi::{Frequency, Layout, Report};
+use crate::commands::statements::Statement;
+use crate::commands::{
+    accounts, balance, cleared, codes, commodities, equity, gains, loan, networth, payees, prices,
+    reconcile, register, roi, statements, statistics, tags, trends,
+};
 use crate::error::{MissingFileError, TimeParseError};
 use crate::models::Ledger;
-use chrono::NaiveDate;
+use chrono::{Datelike, NaiveDate};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -33,13 +39,106 @@ enum Command {
         /// Do not show total
         #[structopt(long = "--no-total")]
         no_total: bool,
+        /// Add a column with the cost of the postings when they were made
+        #[structopt(long = "--historical-cost")]
+        historical_cost: bool,
+        /// Add a column with the share of the balance of the parent account
+        #[structopt(long = "--percent", short = "-%")]
+        percent: bool,
+        /// Add a column with the share of the total balance
+        #[structopt(long = "--share-of-total")]
+        share_of_total: bool,
+        /// Divide every balance by the number of days of the report
+        #[structopt(long = "--daily-average")]
+        daily_average: bool,
     },
 
     #[structopt(alias = "reg")]
-    Register(CommonOpts),
+    Register {
+        #[structopt(flatten)]
+        options: CommonOpts,
+        /// Show the running mean of the postings instead of the running total
+        #[structopt(long = "--average", short = "-A")]
+        average: bool,
+        /// Show how far every posting is from the running mean
+        #[structopt(long = "--deviation")]
+        deviation: bool,
+    },
+    /// Cleared and total balance of every account
+    Cleared(CommonOpts),
+    /// Capital gains of every disposal, as CSV
+    #[structopt(alias = "gains")]
+    CapitalGains {
+        #[structopt(flatten)]
+        options: CommonOpts,
+        /// The cost basis is the average cost rather than the cost of the oldest lots
+        #[structopt(long = "--average-cost")]
+        average_cost: bool,
+        /// Days a lot has to be held for the gain to be long term
+        #[structopt(long = "--holding-period", default_value = "365")]
+        holding_period: i64,
+    },
+    /// Mark the postings of an account as cleared until they match a bank statement
+    Reconcile {
+        #[structopt(flatten)]
+        options: CommonOpts,
+        /// The balance of the statement, like "1234.56 EUR"
+        #[structopt(long = "--statement-balance", allow_hyphen_values = true)]
+        statement_balance: String,
+        /// Date of the statement, later postings are not listed
+        #[structopt(long = "--date", parse(try_from_str = date_parser))]
+        date: Option<NaiveDate>,
+    },
+    /// Amortization schedule of a loan, as journal text
+    Loan {
+        #[structopt(flatten)]
+        options: CommonOpts,
+        #[structopt(flatten)]
+        period_grouping: PeriodGroup,
+        /// The amount borrowed, like "200000 EUR"
+        #[structopt(long = "--principal")]
+        principal: String,
+        /// Yearly interest rate, as a percentage
+        #[structopt(long = "--rate")]
+        rate: String,
+        /// Number of payments, monthly unless -Q or -Y are given
+        #[structopt(long = "--term")]
+        term: u32,
+        /// Date of the first payment
+        #[structopt(long = "--start", parse(try_from_str = date_parser))]
+        start: NaiveDate,
+        /// Payee of the payments
+        #[structopt(long = "--payee", default_value = "Loan payment")]
+        payee: String,
+        /// Account of the debt
+        #[structopt(long = "--loan-account", default_value = "Liabilities:Loan")]
+        loan_account: String,
+        /// Account of the interest
+        #[structopt(long = "--interest-account", default_value = "Expenses:Interest")]
+        interest_account: String,
+        /// Account the payments are made from
+        #[structopt(long = "--payment-account", default_value = "Assets:Bank")]
+        payment_account: String,
+        /// Write virtual postings, to keep the schedule as a forecast
+        #[structopt(long = "--virtual")]
+        virtual_postings: bool,
+        /// Decimals of the amounts, by default those of the commodity or of the principal and at
+        /// least two
+        #[structopt(long = "--precision")]
+        precision: Option<usize>,
+    },
     /// List the accounts
     Accounts(CommonOpts),
-    // Codes,
+    /// List the transaction codes
+    Codes(CommonOpts),
+    /// List the tags
+    Tags {
+        #[structopt(flatten)]
+        options: CommonOpts,
+        /// Show the values of every tag
+        #[structopt(long = "--values")]
+        values: bool,
+    },
     /// List the payees
     Payees(CommonOpts),
     /// Show the exchange rates
@@ -68,12 +167,97 @@ enum Command {
         period_grouping: PeriodGroup,
 
         /// Whether to display as calendar table
-        #[structopt(long = "--calendar")]
+        #[structopt(long = "--calendar", conflicts_with = "by-account")]
         calendar: bool,
+        /// Returns of every account of the assets value query, cut at the given depth
+        #[structopt(long = "--by-account")]
+        by_account: Option<usize>,
+        /// Commodity whose returns are shown next to the TWR
+        #[structopt(long = "--benchmark")]
+        benchmark: Option<String>,
         /// Do not display summary
         #[structopt(long = "--no-summary")]
         no_summary: bool,
     },
+
+    /// Net worth at the end of every period, with a chart
+    #[structopt(alias = "nw")]
+    Networth {
+        #[structopt(flatten)]
+        options: CommonOpts,
+        #[structopt(flatten)]
+        period_grouping: PeriodGroup,
+        /// Break down the net worth by the top level accounts
+        #[structopt(long = "--by-account")]
+        by_account: bool,
+        /// Draw a bar chart instead of a line chart
+        #[structopt(long = "--bars")]
+        bars: bool,
+    },
+
+    /// Monthly amounts of every account, with the month over month and year over year changes
+    Trends {
+        #[structopt(flatten)]
+        options: CommonOpts,
+        /// Draw a heatmap instead of a table with the amounts
+        #[structopt(long = "--heatmap")]
+        heatmap: bool,
+        /// Standard deviations from the mean of the account for a month to be unusual
+        #[structopt(long = "--deviations", default_value = "2")]
+        deviations: f64,
+    },
+
+    /// Balance sheet: assets and liabilities
+    #[structopt(alias = "bs")]
+    Balancesheet {
+        #[structopt(flatten)]
+        options: CommonOpts,
+        #[structopt(flatten)]
+        period_grouping: PeriodGroup,
+        #[structopt(flatten)]
+        sections: StatementSections,
+        /// Do not show totals
+        #[structopt(long = "--no-total")]
+        no_total: bool,
+    },
+    /// Income statement: revenues and expenses
+    #[structopt(alias = "is")]
+    Incomestatement {
+        #[structopt(flatten)]
+        options: CommonOpts,
+        #[structopt(flatten)]
+        period_grouping: PeriodGroup,
+        #[structopt(flatten)]
+        sections: StatementSections,
+        /// Do not show totals
+        #[structopt(long = "--no-total")]
+        no_total: bool,
+    },
+    /// Closing and opening transactions for the balances at a date
+    #[structopt(alias = "close")]
+    Equity {
+        #[structopt(flatten)]
+        options: CommonOpts,
+        /// Account that receives the closing balances
+        #[structopt(long = "--closing-account", default_value = "Equity:Closing Balances")]
+        closing_account: String,
+        /// Account the opening balances come from
+        #[structopt(long = "--opening-account", default_value = "Equity:Opening Balances")]
+        opening_account: String,
+    },
+    /// Cash flow: movements of the cash accounts
+    #[structopt(alias = "cf")]
+    Cashflow {
+        #[structopt(flatten)]
+        options: CommonOpts,
+        #[structopt(flatten)]
+        period_grouping: PeriodGroup,
+        #[structopt(flatten)]
+        sections: StatementSections,
+        /// Do not show totals
+        #[structopt(long = "--no-total")]
+        no_total: bool,
+    },
 }
 
 #[derive(Debug, StructOpt)]
@@ -96,9 +280,16 @@ struct Repl {
 /// Command line options
 #[derive(Debug, StructOpt, Clone)]
 pub struct CommonOpts {
-    /// Input file
-    #[structopt(name = "FILE", short = "f", long = "file", parse(from_os_str))]
-    pub input_file: PathBuf,
+    /// Input file, use - to read from standard input. It can be repeated to read several files
+    #[structopt(
+        name = "FILE",
+        short = "f",
+        long = "file",
+        parse(from_os_str),
+        required = true,
+        number_of_values = 1
+    )]
+    pub input_files: Vec<PathBuf>,
 
     /// Ignore init file if it exists
     #[structopt(long = "--args-only")]
@@ -122,8 +313,9 @@ pub struct CommonOpts {
     pub begin: Option<NaiveDate>,
     #[structopt(short = "e", long = "end", parse(try_from_str = date_parser))]
     pub end: Option<NaiveDate>,
-    #[structopt(short = "p", long = "period")]
-    period: Option<String>,
+    /// Period, for example "2021", "2021-03", "last month" or "from 2021/01/01 to 2021/04/01"
+    #[structopt(short = "p", long = "period", parse(try_from_str = period_parser))]
+    pub period: Option<(NaiveDate, NaiveDate)>,
     #[structopt(long = "now", parse(try_from_str = date_parser))]
     now: Option<NaiveDate>,
 
@@ -131,9 +323,9 @@ pub struct CommonOpts {
     #[structopt(long = "--no-balance-check")]
     pub no_balance_check: bool,
 
-    /// Display the report in the selected currency
-    #[structopt(long = "--exchange", short = "-X")]
-    pub exchange: Option<String>,
+    /// Display the report in the selected currency, the balance report takes more than one
+    #[structopt(long = "--exchange", short = "-X", number_of_values = 1)]
+    pub exchange: Vec<String>,
 
     /// Add a conversion to the selected currency in the output
     #[structopt(long = "--convert", short = "-C")]
@@ -175,6 +367,47 @@ pub struct CommonOpts {
     /// Show the other postings in the transaction
     #[structopt(long = "--related")]
     pub related: bool,
+
+    /// Group the postings by the value of a tag instead of by account
+    #[structopt(long = "--pivot")]
+    pub pivot: Option<String>,
+
+    /// Template for the report lines, like "%(date) %-20(payee) %12(amount)"
+    #[structopt(long = "--format")]
+    pub format: Option<String>,
+    /// Template for the register report, it takes precedence over --format
+    #[structopt(long = "--register-format")]
+    pub register_format: Option<String>,
+    /// Template for the balance report, it takes precedence over --format
+    #[structopt(long = "--balance-format")]
+    pub balance_format: Option<String>,
+
+    /// Sort the report by a value expression, like "date", "payee" or "-amount"
+    #[structopt(long = "--sort", short = "-S", allow_hyphen_values = true)]
+    pub sort: Option<String>,
+    /// Only show the rows for which the value expression is true, totals still include the rest
+    #[structopt(long = "--display")]
+    pub display: Option<String>,
+    /// Only show the first rows of the report
+    #[structopt(long = "--head")]
+    pub head: Option<usize>,
+    /// Only show the last rows of the report
+    #[structopt(long = "--tail")]
+    pub tail: Option<usize>,
+
+    /// Only cleared postings (marked with *)
+    #[structopt(long = "--cleared")]
+    pub cleared: bool,
+    /// Only pending postings (marked with !)
+    #[structopt(long = "--pending")]
+    pub pending: bool,
+    /// Only postings that are neither cleared nor pending
+    #[structopt(long = "--uncleared")]
+    pub uncleared: bool,
+
+    /// Add the periodic transactions after the last transaction, for a year or within --forecast=PERIOD
+    #[structopt(long = "--forecast", require_equals = true, min_values = 0)]
+    pub forecast: Option<Option<String>>,
 }
 
 /// Groups of time
@@ -191,6 +424,36 @@ pub struct PeriodGroup {
     pub monthly: bool,
 }
 
+impl PeriodGroup {
+    /// The frequency, only if a grouping has been asked for
+    pub fn frequency(&self) -> Option<Frequency> {
+        match self.yearly | self.quarterly | self.monthly {
+            true => Some(Frequency::from(self.clone())),
+            false => None,
+        }
+    }
+}
+
+/// Regular expressions for the sections of the financial statements, by default the account types are used
+#[derive(StructOpt, Clone, Debug)]
+pub struct StatementSections {
+    /// Regular expression for the asset accounts
+    #[structopt(long = "--assets-regex")]
+    pub assets: Option<String>,
+    /// Regular expression for the liability accounts
+    #[structopt(long = "--liabilities-regex")]
+    pub liabilities: Option<String>,
+    /// Regular expression for the revenue accounts
+    #[structopt(long = "--revenues-regex")]
+    pub revenues: Option<String>,
+    /// Regular expression for the expense accounts
+    #[structopt(long = "--expenses-regex")]
+    pub expenses: Option<String>,
+    /// Regular expression for the cash accounts
+    #[structopt(long = "--cash-regex")]
+    pub cash: Option<String>,
+}
+
 /// Entry point for the command line app
 const INIT_FILE_FLAG: &str = "--init-file";
 const NO_INIT_FILE_FLAG: &str = "--args-only";
@@ -260,7 +523,7 @@ pub fn run_app(input_args: Vec<String>) -> Result<(), Box<dyn std::error::Error>
                     let duration = start.elapsed();
                     println!(
                         "Loaded ledger from {:?} in {:?}",
-                        &opt.options.input_file, duration
+                        &opt.options.input_files, duration
                     );
 
                     // Start the REPL
@@ -278,7 +541,7 @@ pub fn run_app(input_args: Vec<String>) -> Result<(), Box<dyn std::error::Error>
                                         Ok(j) => {
                                             println!(
                                                 "Loaded journal from {:?} in {:?}",
-                                                &opt.options.input_file, duration
+                                                &opt.options.input_files, duration
                                             );
                                             ledger = j;
                                         }
@@ -391,17 +654,37 @@ fn execute_command(
             options,
             flat,
             no_total,
+            historical_cost,
+            percent,
+            share_of_total,
+            daily_average,
         } => {
             if options.force_color {
                 env::set_var("CLICOLOR_FORCE", "1");
             }
-            balance::execute(&options, maybe_ledger, flat, !no_total)
+            let extra = ExtraColumns {
+                historical_cost,
+                percent,
+                share_of_total,
+            };
+            balance::execute(
+                &options,
+                maybe_ledger,
+                flat,
+                !no_total,
+                extra,
+                daily_average,
+            )
         }
-        Command::Register(options) => {
+        Command::Register {
+            options,
+            average,
+            deviation,
+        } => {
             if options.force_color {
                 env::set_var("CLICOLOR_FORCE", "1");
             }
-            register::execute(&options, maybe_ledger)
+            register::execute(&options, maybe_ledger, average, deviation)
         }
 
         Command::ReturnOnInvestment {
@@ -410,21 +693,166 @@ fn execute_command(
             assets_value,
             period_grouping,
             calendar,
+            by_account,
+            benchmark,
             no_summary,
         } => {
             if options.force_color {
                 env::set_var("CLICOLOR_FORCE", "1");
             }
+            let layout = match (by_account, calendar) {
+                (Some(depth), _) => Layout::ByAccount(depth),
+                (None, true) => Layout::Calendar,
+                (None, false) => Layout::Periods,
+            };
+            let report = Report {
+                layout,
+                summary: !no_summary,
+                benchmark,
+            };
             roi::execute(
                 &options,
                 maybe_ledger,
                 cash_flows,
                 assets_value,
                 Frequency::from(period_grouping),
-                calendar,
-                !no_summary,
+                &report,
+            )
+        }
+        Command::Networth {
+            options,
+            period_grouping,
+            by_account,
+            bars,
+        } => {
+            if options.force_color {
+                env::set_var("CLICOLOR_FORCE", "1");
+            }
+            networth::execute(
+                &options,
+                maybe_ledger,
+                Frequency::from(period_grouping),
+                by_account,
+                bars,
             )
         }
+        Command::Trends {
+            options,
+            heatmap,
+            deviations,
+        } => {
+            if options.force_color {
+                env::set_var("CLICOLOR_FORCE", "1");
+            }
+            trends::execute(&options, maybe_ledger, heatmap, deviations)
+        }
+        Command::Balancesheet {
+            options,
+            period_grouping,
+            sections,
+            no_total,
+        } => {
+            if options.force_color {
+                env::set_var("CLICOLOR_FORCE", "1");
+            }
+            statements::execute(
+                &options,
+                maybe_ledger,
+                Statement::BalanceSheet,
+                period_grouping.frequency(),
+                &sections,
+                !no_total,
+            )
+        }
+        Command::Incomestatement {
+            options,
+            period_grouping,
+            sections,
+            no_total,
+        } => {
+            if options.force_color {
+                env::set_var("CLICOLOR_FORCE", "1");
+            }
+            statements::execute(
+                &options,
+                maybe_ledger,
+                Statement::IncomeStatement,
+                period_grouping.frequency(),
+                &sections,
+                !no_total,
+            )
+        }
+        Command::Cashflow {
+            options,
+            period_grouping,
+            sections,
+            no_total,
+        } => {
+            if options.force_color {
+                env::set_var("CLICOLOR_FORCE", "1");
+            }
+            statements::execute(
+                &options,
+                maybe_ledger,
+                Statement::CashFlow,
+                period_grouping.frequency(),
+                &sections,
+                !no_total,
+            )
+        }
+        Command::CapitalGains {
+            options,
+            average_cost,
+            holding_period,
+        } => gains::execute(&options, maybe_ledger, average_cost, holding_period),
+        Command::Reconcile {
+            options,
+            statement_balance,
+            date,
+        } => {
+            if options.force_color {
+                env::set_var("CLICOLOR_FORCE", "1");
+            }
+            reconcile::execute(&options, maybe_ledger, &statement_balance, date)
+        }
+        Command::Loan {
+            options,
+            period_grouping,
+            principal,
+            rate,
+            term,
+            start,
+            payee,
+            loan_account,
+            interest_account,
+            payment_account,
+            virtual_postings,
+            precision,
+        } => {
+            let loan = Loan {
+                principal,
+                rate,
+                payments: term,
+                start,
+                frequency: period_grouping.frequency().unwrap_or(Frequency::Monthly),
+                payee,
+                loan_account,
+                interest_account,
+                payment_account,
+                precision,
+            };
+            loan::execute(&options, maybe_ledger, &loan, virtual_postings)
+        }
+        Command::Equity {
+            options,
+            closing_account,
+            opening_account,
+        } => {
+            if options.force_color {
+                env::set_var("CLICOLOR_FORCE", "1");
+            }
+            equity::execute(&options, maybe_ledger, &closing_account, &opening_account)
+        }
         Command::Commodities(options) => {
             if options.force_color {
                 env::set_var("CLICOLOR_FORCE", "1");
@@ -447,6 +875,24 @@ fn execute_command(
 
             accounts::execute(&options, maybe_ledger)
         }
+        Command::Cleared(options) => {
+            if options.force_color {
+                env::set_var("CLICOLOR_FORCE", "1");
+            }
+            cleared::execute(&options, maybe_ledger)
+        }
+        Command::Codes(options) => {
+            if options.force_color {
+                env::set_var("CLICOLOR_FORCE", "1");
+            }
+            codes::execute(&options, maybe_ledger)
+        }
+        Command::Tags { options, values } => {
+            if options.force_color {
+                env::set_var("CLICOLOR_FORCE", "1");
+            }
+            tags::execute(&options, maybe_ledger, values)
+        }
         Command::Statistics(options) => {
             if options.force_color {
                 env::set_var("CLICOLOR_FORCE", "1");
@@ -491,10 +937,78 @@ pub fn date_parser(date: &str) -> Result<NaiveDate, Box<dyn std::error::Error>>
     }
 }
 
+/// A parser for period expressions, returns the first day of the period and the first day after it
+pub fn period_parser(period: &str) -> Result<(NaiveDate, NaiveDate), Box<dyn std::error::Error>> {
+    lazy_static! {
+        static ref RE_YEAR: Regex = Regex::new(r"^(\d{4})$").unwrap();
+        static ref RE_MONTH: Regex = Regex::new(r"^(\d{4})[/-](\d\d?)$").unwrap();
+        static ref RE_DATE: Regex = Regex::new(r"^(\d{4})[/-](\d\d?)[/-](\d\d?)$").unwrap();
+    }
+    let date = period.trim();
+    if RE_YEAR.is_match(date) {
+        let begin = date_parser(date)?;
+        Ok((
+            begin,
+            NaiveDate::from_ymd_opt(begin.year() + 1, 1, 1).unwrap(),
+        ))
+    } else if RE_MONTH.is_match(date) {
+        let begin = date_parser(date)?;
+        let end = match begin.month() {
+            12 => NaiveDate::from_ymd_opt(begin.year() + 1, 1, 1),
+            month => NaiveDate::from_ymd_opt(begin.year(), month + 1, 1),
+        };
+        Ok((begin, end.unwrap()))
+    } else if RE_DATE.is_match(date) {
+        let begin = date_parser(date)?;
+        Ok((begin, begin.succ_opt().unwrap()))
+    } else {
+        match two_timer::parse(date, None) {
+            Ok((t1, t2, _b)) => Ok((t1.date(), t2.date())),
+            Err(e) => {
+                eprintln!("{:?}", e);
+                Err(Box::new(TimeParseError {}))
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn command_line_periods() {
+        assert_eq!(
+            period_parser("2021").unwrap(),
+            (
+                NaiveDate::from_ymd_opt(2021, 1, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2022, 1, 1).unwrap()
+            )
+        );
+        assert_eq!(
+            period_parser("2021-12").unwrap(),
+            (
+                NaiveDate::from_ymd_opt(2021, 12, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2022, 1, 1).unwrap()
+            )
+        );
+        assert_eq!(
+            period_parser("2021/03/05").unwrap(),
+            (
+                NaiveDate::from_ymd_opt(2021, 3, 5).unwrap(),
+                NaiveDate::from_ymd_opt(2021, 3, 6).unwrap()
+            )
+        );
+        assert_eq!(
+            period_parser("march 2021").unwrap(),
+            (
+                NaiveDate::from_ymd_opt(2021, 3, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2021, 4, 1).unwrap()
+            )
+        );
+        assert!(period_parser("this is not a period").is_err());
+    }
+
     #[test]
     fn command_line_dates() {
         assert_eq!(
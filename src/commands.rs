@@ -1,8 +1,20 @@
 pub mod accounts;
 pub mod balance;
+pub mod cleared;
+pub mod codes;
 pub mod commodities;
+pub mod equity;
+pub mod format;
+pub mod gains;
+pub mod loan;
+pub mod networth;
 pub mod payees;
 pub mod prices;
+pub mod reconcile;
 pub mod register;
+pub mod report;
 pub mod roi;
+pub mod statements;
 pub mod statistics;
+pub mod tags;
+pub mod trends;
@@ -1,7 +1,8 @@
 use std::convert::TryFrom;
 use std::error::Error;
 
-use crate::models::{Account, HasName, Ledger};
+use crate::commands::report::{summary_posting, Rows};
+use crate::models::{Account, HasName, Ledger, Money};
 use crate::CommonOpts;
 use std::ops::Deref;
 
@@ -16,7 +17,24 @@ pub fn execute(options: &CommonOpts, maybe_ledger: Option<Ledger>) -> Result<(),
         .map(|x| x.1.deref().to_owned())
         .collect::<Vec<Account>>();
     accounts.sort_by(|a, b| a.get_name().cmp(b.get_name()));
-    for acc in accounts {
+
+    let rows = Rows::new(options)?;
+    let mut displayed = vec![];
+    for account in accounts.into_iter() {
+        let (p, t) = summary_posting(account.get_name(), Money::Zero, "");
+        if rows.is_displayed(&p, &t, &ledger.commodities)? {
+            displayed.push(account);
+        }
+    }
+    accounts = displayed;
+    if rows.is_sorted() {
+        accounts = rows.sort(
+            accounts,
+            |account| summary_posting(account.get_name(), Money::Zero, ""),
+            &ledger.commodities,
+        )?;
+    }
+    for acc in rows.limit(accounts) {
         println!("{}", acc);
     }
     Ok(())
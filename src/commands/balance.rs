@@ -2,10 +2,15 @@ use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 
 use colored::Colorize;
-use num::ToPrimitive;
+use num::{BigInt, Signed, ToPrimitive};
 
+use crate::commands::format::{Context, Template};
+use crate::commands::report::{summary_posting, Rows, SortKey};
+use crate::error::MixedCommoditiesError;
 use crate::error::ReportError::CurrencyConversionError;
-use crate::models::{conversion, Account, Balance, Currency, HasName, Ledger, Money};
+use crate::models::{
+    conversion, Account, Balance, Cost, Currency, HasName, Ledger, Money, Posting, PostingOrigin,
+};
 use crate::parser::value_expr::build_root_node_from_expression;
 use crate::{filter, CommonOpts};
 use chrono::Utc;
@@ -13,50 +18,156 @@ use num::rational::BigRational;
 use std::ops::Deref;
 use std::rc::Rc;
 
+/// Footnote of the accounts marked with `*`
+const FORECAST_NOTE: &str = "* includes forecast postings";
+
+/// Optional columns of the balance report
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExtraColumns {
+    /// What was paid for the postings
+    pub historical_cost: bool,
+    /// Share of the balance of the parent account
+    pub percent: bool,
+    /// Share of the total balance
+    pub share_of_total: bool,
+}
+
 /// Balance report
+///
+/// With more than one `--exchange`, `--exchange` and `--convert` together or `--historical-cost`
+/// there is a column for every currency, and one for the historical cost. With `daily_average`
+/// every balance is divided by the number of days of the report.
 pub fn execute(
     options: &CommonOpts,
     maybe_ledger: Option<Ledger>,
     flat: bool,
     show_total: bool,
+    extra: ExtraColumns,
+    daily_average: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    assert!(
-        !(options.convert.is_some() && options.exchange.is_some()),
-        "Incompatible arguments --convert and --exchange"
-    );
+    let historical_cost = extra.historical_cost;
     let ledger = match maybe_ledger {
         Some(ledger) => ledger,
         None => Ledger::try_from(options)?,
     };
 
     let depth = options.depth;
-    let mut balances: HashMap<Rc<Account>, Balance> = HashMap::new();
-
-    // Build a cache of abstract value trees, it takes time to parse expressions, so better do it only once
-    let mut regexes = HashMap::new();
-    let query = filter::preprocess_query(&options.query, &options.related);
-    let node = if query.len() > 2 {
-        Some(build_root_node_from_expression(
-            query.as_str(),
-            &mut regexes,
-        ))
-    } else {
-        None
+    let mut balances = account_balances(options, &ledger)?;
+    let multi_column = (options.exchange.len() > 1)
+        | (options.convert.is_some() & !options.exchange.is_empty())
+        | historical_cost;
+    let date = match &options.end {
+        Some(date) => *date,
+        None => Utc::now().naive_local().date(),
     };
-
-    for t in ledger.transactions.iter() {
-        for p in t.postings.borrow().iter() {
-            if !filter::filter(options, &node, t, p, &ledger.commodities)? {
-                continue;
+    // (currency, multipliers) of every column
+    let mut columns = vec![];
+    if multi_column {
+        for currency_string in options.exchange.iter().chain(options.convert.iter()) {
+            let currency = ledger.commodities.get(currency_string)?;
+            if !columns.iter().any(|(c, _)| c == currency) {
+                columns.push((
+                    currency.clone(),
+                    conversion(currency.clone(), date, &ledger.prices),
+                ));
             }
-            let mut cur_bal = balances
-                .get(p.account.deref())
-                .unwrap_or(&Balance::new())
-                .to_owned();
-            cur_bal = cur_bal + Balance::from(p.amount.as_ref().unwrap().clone());
-            balances.insert(p.account.clone(), cur_bal.to_owned());
         }
     }
+    let mut costs = match historical_cost {
+        true => balances_by_account(options, &ledger, posting_cost)?,
+        false => HashMap::new(),
+    };
+    // The accounts with forecast postings, marked in the report
+    let forecast: Vec<String> = match options.forecast.is_some() {
+        true => balances_by_account(options, &ledger, forecast_amount)?
+            .iter()
+            .filter(|(_, balance)| !balance.is_zero())
+            .map(|(account, _)| account.get_name().to_string())
+            .collect(),
+        false => vec![],
+    };
+    if daily_average {
+        let days = BigRational::from_integer(BigInt::from(report_days(options, &ledger)));
+        for balance in balances.values_mut().chain(costs.values_mut()) {
+            *balance = balance.clone() / days.clone();
+        }
+    }
+    // The percentages are of the balances in the first -X currency, including the children
+    let percent_currency = match options.exchange.first() {
+        Some(currency) => {
+            let currency = ledger.commodities.get(currency)?;
+            Some((
+                currency.clone(),
+                conversion(currency.clone(), date, &ledger.prices),
+            ))
+        }
+        None => None,
+    };
+    let in_percent_currency = |balance: &Balance| match &percent_currency {
+        Some((currency, multipliers)) => convert_balance(balance, multipliers, currency),
+        None => Ok(balance.clone()),
+    };
+    let mut tree_totals: HashMap<String, Balance> = HashMap::new();
+    let mut grand_total = Balance::new();
+    if extra.percent | extra.share_of_total {
+        for (account, balance) in balances.iter() {
+            let balance = in_percent_currency(balance)?;
+            grand_total = grand_total + balance.clone();
+            let parts: Vec<&str> = account.get_name().split(':').collect();
+            for n in 1..=parts.len() {
+                let total = tree_totals.entry(parts[..n].join(":")).or_default();
+                *total = total.clone() + balance.clone();
+            }
+        }
+    }
+    // (share of the parent, share of the total) of a line of the report
+    let shares = |account: &str,
+                  balance: &Balance|
+     -> Result<(Option<f64>, Option<f64>), Box<dyn std::error::Error>> {
+        let balance = in_percent_currency(balance)?;
+        let parent = match account.rsplit_once(':') {
+            Some((parent, _)) => tree_totals.get(parent).cloned().unwrap_or_default(),
+            None => grand_total.clone(),
+        };
+        Ok((
+            share(account, &balance, &parent)?,
+            share(account, &balance, &grand_total)?,
+        ))
+    };
+
+    // The historical cost of a line of the report
+    let cost_of = |account: &str| {
+        let prefix = format!("{}:", account);
+        costs
+            .iter()
+            .filter(|(acc, _)| {
+                (acc.get_name() == account)
+                    | (!(flat & depth.is_none()) & acc.get_name().starts_with(&prefix))
+            })
+            .fold(Balance::new(), |acc, x| acc + x.1.clone())
+    };
+    // Whether a line of the report includes forecast postings
+    let includes_forecast = |account: &str| {
+        let prefix = format!("{}:", account);
+        forecast
+            .iter()
+            .any(|acc| (acc == account) | (!(flat & depth.is_none()) & acc.starts_with(&prefix)))
+    };
+    let rows = Rows::new(options)?;
+    // The lines of the balance report are not about a posting, so only the account fields are available
+    let template = match options.balance_format.as_ref().or(options.format.as_ref()) {
+        Some(format) => {
+            let template = Template::parse(format)?;
+            template.check_fields(&["account", "depth", "total", "amount"])?;
+            Some(template)
+        }
+        None => None,
+    };
+    let context = Context {
+        posting: None,
+        commodities: &ledger.commodities,
+        date_format: &options.date_format,
+    };
 
     // For printing this out, take into account whether it is a flat report or not
     // if it is not, the parent balances have to be updated
@@ -142,22 +253,12 @@ pub fn execute(
 
     // Print the balances by account
     let mut multipliers = HashMap::new();
-    if let Some(currency_string) = &options.convert {
-        let date = if let Some(date) = &options.end {
-            *date
-        } else {
-            Utc::now().naive_local().date()
-        };
+    if let Some(currency_string) = options.convert.as_ref().filter(|_| !multi_column) {
         if let Ok(currency) = ledger.commodities.get(currency_string) {
             multipliers = conversion(currency.clone(), date, &ledger.prices);
         }
     }
-    if let Some(currency_string) = &options.exchange {
-        let date = if let Some(date) = &options.end {
-            *date
-        } else {
-            Utc::now().naive_local().date()
-        };
+    if let Some(currency_string) = options.exchange.first().filter(|_| !multi_column) {
         if let Ok(currency) = ledger.commodities.get(currency_string) {
             multipliers = conversion(currency.clone(), date, &ledger.prices);
             let mut updated_balances = Vec::new();
@@ -169,9 +270,73 @@ pub fn execute(
     }
 
     vec_balances.sort_by(|a, b| a.0.cmp(b.0));
+    if let Some(depth) = depth {
+        vec_balances.retain(|(account, _)| account.split(':').count() <= depth);
+    }
+    // The expressions are evaluated on the balance as a single amount
+    let summary = |account: &str, balance: &Balance| {
+        balance
+            .to_money()
+            .map(|money| summary_posting(account, money, ""))
+            .map_err(|_| MixedCommoditiesError(account.to_string()))
+    };
+    if rows.is_filtered() {
+        let mut displayed = vec![];
+        for (account, balance) in vec_balances.into_iter() {
+            let (p, t) = summary(account, &balance)?;
+            if rows.is_displayed(&p, &t, &ledger.commodities)? {
+                displayed.push((account, balance));
+            }
+        }
+        vec_balances = displayed;
+    }
+    if rows.is_sorted() {
+        let mut keys: HashMap<&str, SortKey> = HashMap::new();
+        for (account, balance) in vec_balances.iter() {
+            let (p, t) = summary(account, balance)?;
+            keys.insert(
+                account,
+                rows.sort_key(&p, &t, &ledger.commodities)?.unwrap(),
+            );
+        }
+        // In the tree, the accounts are sorted among their siblings and stay under their parents
+        let path = |account: &str| -> Vec<(Option<SortKey>, String)> {
+            let parts: Vec<&str> = account.split(':').collect();
+            (1..=parts.len())
+                .map(|n| parts[..n].join(":"))
+                .filter(|prefix| !flat | (prefix == account))
+                .map(|prefix| (keys.get(prefix.as_str()).cloned(), prefix))
+                .collect()
+        };
+        vec_balances.sort_by_cached_key(|(account, _)| path(account));
+    }
+    vec_balances = rows.limit(vec_balances);
+    // Computed before printing anything, in case a balance has more than one commodity
+    let mut line_shares = HashMap::new();
+    if extra.percent | extra.share_of_total {
+        for (account, balance) in vec_balances.iter() {
+            line_shares.insert(*account, shares(account, balance)?);
+        }
+    }
+    if multi_column & template.is_none() {
+        for (currency, _) in columns.iter() {
+            print!("{:>20}", currency.get_name());
+        }
+        if historical_cost {
+            print!("{:>20}", "Cost");
+        }
+        if extra.percent {
+            print!("{:>10}", "%");
+        }
+        if extra.share_of_total {
+            print!("{:>10}", "% total");
+        }
+        println!();
+    }
     let num_bal = vec_balances.len();
     let mut index = 0;
     let mut showed_balances = 0;
+    let mut showed_forecast = false;
     while index < num_bal {
         let (account, bal) = &vec_balances[index];
         if let Some(depth) = depth {
@@ -186,48 +351,12 @@ pub fn execute(
         }
         showed_balances += 1;
 
-        let mut first = true;
-        for (_, money) in bal.balance.iter() {
-            if !first {
-                println!();
-            }
-            first = false;
-            match money.is_negative() {
-                true => print!("{:>20}", format!("{}", money).red()),
-                false => print!("{:>20}", format!("{}", money)),
-            }
-
-            if let Some(currency_string) = &options.convert {
-                let date = if let Some(date) = &options.end {
-                    *date
-                } else {
-                    Utc::now().naive_local().date()
-                };
-                if let Ok(currency) = ledger.commodities.get(currency_string) {
-                    multipliers = conversion(currency.clone(), date, &ledger.prices);
-
-                    let other_money =
-                        convert_balance(&(money.clone() + Money::Zero), &multipliers, currency)?
-                            .to_money()?;
-
-                    match money.is_negative() {
-                        true => print!("{:>20}", format!("{}", other_money).red()),
-                        false => print!("{:>20}", format!("{}", other_money)),
-                    }
-                }
-            }
-        }
-        if first {
-            // This means the balance was empty
-            print!("{:>20}", "0");
-        }
-        if flat {
-            println!("  {}", account.blue());
+        // The name of the account as displayed, in the tree the parents with only one child are collapsed
+        let (depth, text) = if flat {
+            (account.split(':').count(), account.to_string())
         } else {
             let mut n = account.split(':').count();
-            for _ in 0..n {
-                print!("  ");
-            }
+            let depth = n;
             // start by getting the account name
             let mut text = account.split(':').last().unwrap().to_string();
             // This is where it gets tricky, we need to collapse while we can
@@ -260,7 +389,87 @@ pub fn execute(
                     break;
                 }
             }
-            println!("{}", text.blue());
+            (depth, text)
+        };
+
+        if let Some(template) = &template {
+            let mut values = HashMap::new();
+            let indent = match flat {
+                true => String::new(),
+                false => "  ".repeat(depth - 1),
+            };
+            values.insert("account", format!("{}{}", indent, text));
+            values.insert("depth", depth.to_string());
+            values.insert("total", balance_text(bal));
+            values.insert("amount", balance_text(bal));
+            print!("{}", template.render(&values, &context)?);
+            index += 1;
+            continue;
+        }
+
+        let mut first = true;
+        if multi_column {
+            let mut cells = vec![];
+            for (currency, multipliers) in columns.iter() {
+                cells.push(balance_cell(&convert_balance(bal, multipliers, currency)?));
+            }
+            if historical_cost {
+                cells.push(balance_cell(&cost_of(account)));
+            }
+            print_cells(&cells);
+            first = false;
+        }
+        for (_, money) in bal.balance.iter().filter(|_| !multi_column) {
+            if !first {
+                println!();
+            }
+            first = false;
+            match money.is_negative() {
+                true => print!("{:>20}", format!("{}", money).red()),
+                false => print!("{:>20}", format!("{}", money)),
+            }
+
+            if let Some(currency_string) = &options.convert {
+                if let Ok(currency) = ledger.commodities.get(currency_string) {
+                    multipliers = conversion(currency.clone(), date, &ledger.prices);
+
+                    let other_money =
+                        convert_balance(&(money.clone() + Money::Zero), &multipliers, currency)?
+                            .to_money()?;
+
+                    match money.is_negative() {
+                        true => print!("{:>20}", format!("{}", other_money).red()),
+                        false => print!("{:>20}", format!("{}", other_money)),
+                    }
+                }
+            }
+        }
+        if first {
+            // This means the balance was empty
+            print!("{:>20}", "0");
+        }
+        let (of_parent, of_total) = line_shares.get(account).cloned().unwrap_or_default();
+        if extra.percent {
+            print!("{:>10}", percentage(of_parent));
+        }
+        if extra.share_of_total {
+            print!("{:>10}", percentage(of_total));
+        }
+        // Like in the register, forecasts are in italics
+        let text = match includes_forecast(account) {
+            true => {
+                showed_forecast = true;
+                format!("{} *", text).blue().italic()
+            }
+            false => text.blue(),
+        };
+        if flat {
+            println!("  {}", text);
+        } else {
+            for _ in 0..depth {
+                print!("  ");
+            }
+            println!("{}", text);
         }
         index += 1;
     }
@@ -271,17 +480,50 @@ pub fn execute(
         let mut total_balance = balances
             .iter()
             .fold(Balance::new(), |acc, x| acc + x.1.to_owned());
-        print!("--------------------");
-        if !multipliers.is_empty() & options.exchange.is_some() {
+        if !multipliers.is_empty() & !options.exchange.is_empty() & !multi_column {
             total_balance = convert_balance(
                 &total_balance,
                 &multipliers,
                 ledger
                     .commodities
-                    .get(options.exchange.as_ref().unwrap().as_str())
+                    .get(options.exchange[0].as_str())
                     .unwrap(),
             )?;
         }
+        if let Some(template) = &template {
+            let mut values = HashMap::new();
+            values.insert("account", String::new());
+            values.insert("depth", "0".to_string());
+            values.insert("total", balance_text(&total_balance));
+            values.insert("amount", balance_text(&total_balance));
+            print!("{}", template.render(&values, &context)?);
+            return Ok(());
+        }
+        if multi_column {
+            let count = columns.len() + historical_cost as usize;
+            println!("{}", "--------------------".repeat(count));
+            let mut cells = vec![];
+            for (currency, multipliers) in columns.iter() {
+                cells.push(balance_cell(&convert_balance(
+                    &total_balance,
+                    multipliers,
+                    currency,
+                )?));
+            }
+            if historical_cost {
+                let total_cost = costs
+                    .values()
+                    .fold(Balance::new(), |acc, x| acc + x.clone());
+                cells.push(balance_cell(&total_cost));
+            }
+            print_cells(&cells);
+            println!();
+            if showed_forecast {
+                println!("{}", FORECAST_NOTE);
+            }
+            return Ok(());
+        }
+        print!("--------------------");
         if total_balance.is_zero() {
             print!("\n{:>20}", "0");
         } else {
@@ -322,11 +564,197 @@ pub fn execute(
         }
         println!();
     }
+    if showed_forecast {
+        println!("{}", FORECAST_NOTE);
+    }
 
     // We're done :)
     Ok(())
 }
 
+/// Every currency of the balance, separated by commas
+fn balance_text(balance: &Balance) -> String {
+    match balance.is_zero() {
+        true => "0".to_string(),
+        false => sorted_moneys(balance)
+            .iter()
+            .map(|x| format!("{}", x))
+            .collect::<Vec<String>>()
+            .join(", "),
+    }
+}
+
+/// The commodities of the balance that are not zero, sorted by name
+fn sorted_moneys(balance: &Balance) -> Vec<&Money> {
+    let mut moneys: Vec<&Money> = balance
+        .iter()
+        .filter(|x| !x.1.is_zero())
+        .map(|x| x.1)
+        .collect();
+    moneys.sort_by_key(|x| x.get_commodity().map(|c| c.get_name().to_string()));
+    moneys
+}
+
+/// The share of `balance` in `total`, if they are in the same single commodity
+fn share(
+    account: &str,
+    balance: &Balance,
+    total: &Balance,
+) -> Result<Option<f64>, MixedCommoditiesError> {
+    let error = || MixedCommoditiesError(account.to_string());
+    let (balance, total) = match (balance.to_money(), total.to_money()) {
+        (Ok(balance), Ok(total)) => (balance, total),
+        _ => return Err(error()),
+    };
+    if total.is_zero() {
+        return Ok(None);
+    }
+    if balance
+        .get_commodity()
+        .is_some_and(|c| Some(c) != total.get_commodity())
+    {
+        return Err(error());
+    }
+    Ok((balance.get_amount() / total.get_amount()).to_f64())
+}
+
+/// A fraction as a percentage, for all the reports, or `n/a` if it can't be computed
+pub(crate) fn percentage(value: Option<f64>) -> String {
+    match value {
+        Some(value) => format!("{:.2}%", value * 100.0),
+        None => "n/a".to_string(),
+    }
+}
+
+/// The lines of a column of the multi-column report, one per commodity, in red if it is negative
+///
+/// There is always a space before every line, even if it does not fit in the column.
+fn balance_cell(balance: &Balance) -> Vec<String> {
+    let moneys = sorted_moneys(balance);
+    if moneys.is_empty() {
+        return vec![format!(" {:>19}", "0")];
+    }
+    moneys
+        .iter()
+        .map(|money| match money.is_negative() {
+            true => format!(" {:>19}", format!("{}", money).red()),
+            false => format!(" {:>19}", format!("{}", money)),
+        })
+        .collect()
+}
+
+/// Prints the cells side by side, the account goes after the last line
+fn print_cells(cells: &[Vec<String>]) {
+    let height = cells.iter().map(|cell| cell.len()).max().unwrap_or(0);
+    for line in 0..height {
+        if line > 0 {
+            println!();
+        }
+        for cell in cells.iter() {
+            match cell.get(line) {
+                Some(text) => print!("{}", text),
+                None => print!("{:20}", ""),
+            }
+        }
+    }
+}
+
+/// The amount of a forecast posting, zero for the rest
+fn forecast_amount(posting: &Posting) -> Money {
+    match posting.origin {
+        PostingOrigin::Periodic => posting.amount.clone().unwrap(),
+        _ => Money::Zero,
+    }
+}
+
+/// What was paid for the posting: its cost if it has one, otherwise its amount
+fn posting_cost(posting: &Posting) -> Money {
+    let amount = posting.amount.clone().unwrap();
+    let (cost, quantity) = match &posting.cost {
+        Some(Cost::PerUnit { amount: cost }) => (cost, amount.get_amount()),
+        Some(Cost::Total { amount: cost }) => (cost, amount.get_amount().signum()),
+        None => return amount,
+    };
+    match cost.get_commodity() {
+        Some(currency) => Money::from((currency, cost.get_amount().abs() * quantity)),
+        None => amount,
+    }
+}
+
+/// The number of days of the report, from the dates in the options or else from the first to the
+/// last transaction of the journal
+fn report_days(options: &CommonOpts, ledger: &Ledger) -> i64 {
+    let dates = ledger.transactions.iter().filter_map(|t| t.date);
+    let begin = [options.begin, options.period.map(|x| x.0)]
+        .iter()
+        .flatten()
+        .max()
+        .copied()
+        .or_else(|| dates.clone().min());
+    let end = [options.end, options.period.map(|x| x.1)]
+        .iter()
+        .flatten()
+        .min()
+        .copied()
+        .or_else(|| dates.max().and_then(|x| x.succ_opt()));
+    match (begin, end) {
+        (Some(begin), Some(end)) => (end - begin).num_days().max(1),
+        _ => 1,
+    }
+}
+
+/// Balance of every account for the postings that pass the filters of the options
+pub(crate) fn account_balances(
+    options: &CommonOpts,
+    ledger: &Ledger,
+) -> Result<HashMap<Rc<Account>, Balance>, Box<dyn std::error::Error>> {
+    // Exchanged, fixated lots are worth their lot price
+    match options.exchange.is_empty() {
+        true => balances_by_account(options, ledger, |p| p.amount.clone().unwrap()),
+        false => balances_by_account(options, ledger, Posting::exchange_amount),
+    }
+}
+
+/// The sum of a value of the postings that pass the filters, by account
+fn balances_by_account(
+    options: &CommonOpts,
+    ledger: &Ledger,
+    value: fn(&Posting) -> Money,
+) -> Result<HashMap<Rc<Account>, Balance>, Box<dyn std::error::Error>> {
+    let mut balances: HashMap<Rc<Account>, Balance> = HashMap::new();
+
+    // Build a cache of abstract value trees, it takes time to parse expressions, so better do it only once
+    let mut regexes = HashMap::new();
+    let query = filter::preprocess_query(&options.query, &options.related)?;
+    let node = if query.len() > 2 {
+        Some(build_root_node_from_expression(
+            query.as_str(),
+            &mut regexes,
+        ))
+    } else {
+        None
+    };
+
+    for t in ledger.transactions.iter() {
+        for p in t.postings.borrow().iter() {
+            if !filter::filter(options, &node, t, p, &ledger.commodities)? {
+                continue;
+            }
+            let account = match &options.pivot {
+                Some(tag) => p.get_pivot_account(tag),
+                None => p.account.clone(),
+            };
+            let mut cur_bal = balances
+                .get(account.deref())
+                .unwrap_or(&Balance::new())
+                .to_owned();
+            cur_bal = cur_bal + Balance::from(value(p));
+            balances.insert(account, cur_bal.to_owned());
+        }
+    }
+    Ok(balances)
+}
+
 pub(crate) fn convert_balance(
     balance: &Balance,
     multipliers: &HashMap<Rc<Currency>, BigRational>,
@@ -0,0 +1,88 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+
+use chrono::NaiveDate;
+use prettytable::format;
+use prettytable::{Cell, Row, Table};
+
+use crate::commands::statements::balance_cell;
+use crate::models::{Balance, Cleared, HasName, Ledger};
+use crate::parser::value_expr::build_root_node_from_expression;
+use crate::{filter, CommonOpts};
+
+/// Cleared report
+///
+/// Shows the cleared and the total balance of every account, along with the date of the last cleared posting
+pub fn execute(
+    options: &CommonOpts,
+    maybe_ledger: Option<Ledger>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = match maybe_ledger {
+        Some(ledger) => ledger,
+        None => Ledger::try_from(options)?,
+    };
+
+    let mut regexes = HashMap::new();
+    let query = filter::preprocess_query(&options.query, &options.related)?;
+    let node = if query.len() > 2 {
+        Some(build_root_node_from_expression(
+            query.as_str(),
+            &mut regexes,
+        ))
+    } else {
+        None
+    };
+
+    // (cleared, total, last cleared date) by account
+    let mut accounts: BTreeMap<String, (Balance, Balance, Option<NaiveDate>)> = BTreeMap::new();
+    for t in ledger.transactions.iter() {
+        for p in t.postings.borrow().iter() {
+            if !filter::filter(options, &node, t, p, &ledger.commodities)? {
+                continue;
+            }
+            let (cleared, total, last_cleared) = accounts
+                .entry(p.account.get_name().to_string())
+                .or_insert_with(|| (Balance::new(), Balance::new(), None));
+            let money = Balance::from(p.amount.clone().unwrap());
+            *total = total.clone() + money.clone();
+            if p.cleared == Cleared::Cleared {
+                *cleared = cleared.clone() + money;
+                *last_cleared = (*last_cleared).max(Some(p.date));
+            }
+        }
+    }
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["Account", r->"Cleared", r->"Total", "Last cleared"]);
+    let mut total_cleared = Balance::new();
+    let mut total = Balance::new();
+    for (account, (cleared, balance, last_cleared)) in accounts.iter() {
+        if balance.is_zero() & cleared.is_zero() {
+            continue;
+        }
+        total_cleared = total_cleared + cleared.clone();
+        total = total + balance.clone();
+        let date = match last_cleared {
+            Some(date) => format!("{}", date.format(&options.date_format)),
+            None => String::new(),
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(account),
+            balance_cell(cleared, false),
+            balance_cell(balance, false),
+            Cell::new(&date),
+        ]));
+    }
+    if table.is_empty() {
+        return Ok(());
+    }
+    table.add_row(Row::new(vec![
+        Cell::new("Total").style_spec("b"),
+        balance_cell(&total_cleared, false).style_spec("rb"),
+        balance_cell(&total, false).style_spec("rb"),
+        Cell::new(""),
+    ]));
+    table.printstd();
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::error::Error;
+
+use crate::commands::tags::Usage;
+use crate::models::Ledger;
+use crate::parser::value_expr::build_root_node_from_expression;
+use crate::{filter, CommonOpts};
+
+/// Lists the transaction codes, with the number of transactions and the dates they were used
+pub fn execute(options: &CommonOpts, maybe_ledger: Option<Ledger>) -> Result<(), Box<dyn Error>> {
+    let ledger = match maybe_ledger {
+        Some(ledger) => ledger,
+        None => Ledger::try_from(options)?,
+    };
+
+    let mut regexes = HashMap::new();
+    let query = filter::preprocess_query(&options.query, &options.related)?;
+    let node = if query.len() > 2 {
+        Some(build_root_node_from_expression(
+            query.as_str(),
+            &mut regexes,
+        ))
+    } else {
+        None
+    };
+
+    let mut codes: BTreeMap<String, Usage> = BTreeMap::new();
+    for t in ledger.transactions.iter() {
+        let code = match &t.code {
+            Some(code) => code,
+            None => continue,
+        };
+        // The transaction is listed if any of its postings passes the filters
+        let mut included = false;
+        for p in t.postings.borrow().iter() {
+            if filter::filter(options, &node, t, p, &ledger.commodities)? {
+                included = true;
+                break;
+            }
+        }
+        if !included {
+            continue;
+        }
+        let date = t.date.unwrap();
+        codes
+            .entry(code.clone())
+            .or_insert_with(|| Usage::new(date))
+            .add(date);
+    }
+
+    for (code, usage) in codes.iter() {
+        usage.print(code, options);
+    }
+    Ok(())
+}
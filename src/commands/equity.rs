@@ -0,0 +1,199 @@
+//! Closing and opening transactions, as in `ledger equity` and `hledger close`
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use chrono::NaiveDate;
+use num::{BigRational, Signed, Zero};
+
+use crate::commands::balance::{account_balances, convert_balance};
+use crate::models::{conversion, Balance, Cost, HasName, Ledger, Money};
+use crate::parser::value_expr::build_root_node_from_expression;
+use crate::{filter, CommonOpts};
+
+/// A quantity of a commodity along with the unit cost it was acquired at
+type Lot = (BigRational, Option<Money>);
+/// Open lots by account and commodity
+type Lots = HashMap<(String, String), Vec<Lot>>;
+
+/// Prints a closing transaction for every account and the opening one that restores the balances
+pub fn execute(
+    options: &CommonOpts,
+    maybe_ledger: Option<Ledger>,
+    closing_account: &str,
+    opening_account: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = match maybe_ledger {
+        Some(ledger) => ledger,
+        None => Ledger::try_from(options)?,
+    };
+    let balances = account_balances(options, &ledger)?;
+
+    // The balances are closed at the last date of the report
+    let closing_date = match [options.end, options.period.map(|x| x.1)]
+        .iter()
+        .flatten()
+        .min()
+    {
+        Some(end) => end.pred_opt().unwrap(),
+        None => match ledger.transactions.iter().filter_map(|t| t.date).max() {
+            Some(date) => date,
+            None => return Ok(()),
+        },
+    };
+    let opening_date = closing_date.succ_opt().unwrap();
+
+    let mut accounts: Vec<(String, Balance)> = balances
+        .iter()
+        .filter(|(_, balance)| !balance.is_zero())
+        .map(|(account, balance)| (account.get_name().to_string(), balance.clone()))
+        .collect();
+    accounts.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // (account, amount, lot cost)
+    let mut postings: Vec<(String, Money, Option<Money>)> = vec![];
+    if let Some(currency_string) = options.exchange.first() {
+        // Prices of the closing date are included
+        let currency = ledger.commodities.get(currency_string)?;
+        let multipliers = conversion(currency.clone(), opening_date, &ledger.prices);
+        for (account, balance) in accounts.iter() {
+            for (_, money) in convert_balance(balance, &multipliers, currency)?.iter() {
+                if !money.is_zero() {
+                    postings.push((account.clone(), money.clone(), None));
+                }
+            }
+        }
+    } else {
+        let lots = lots(options, &ledger)?;
+        for (account, balance) in accounts.iter() {
+            let mut amounts: Vec<&Money> = balance.balance.values().collect();
+            amounts.sort_by_key(|x| x.get_commodity().map(|c| c.get_name().to_string()));
+            for money in amounts {
+                let commodity = match money.get_commodity() {
+                    Some(commodity) => commodity,
+                    None => continue,
+                };
+                let account_lots = lots
+                    .get(&(account.clone(), commodity.get_name().to_string()))
+                    .cloned()
+                    .unwrap_or_default();
+                let quantity = account_lots
+                    .iter()
+                    .fold(BigRational::zero(), |acc, x| acc + x.0.clone());
+                // Lots are kept only when they explain the whole balance
+                if account_lots.iter().any(|x| x.1.is_some()) & (quantity == money.get_amount()) {
+                    for (amount, cost) in account_lots {
+                        postings.push((
+                            account.clone(),
+                            Money::from((commodity.clone(), amount)),
+                            cost,
+                        ));
+                    }
+                } else {
+                    postings.push((account.clone(), money.clone(), None));
+                }
+            }
+        }
+    }
+    if postings.is_empty() {
+        return Ok(());
+    }
+
+    print_transaction(
+        closing_date,
+        "Closing balances",
+        &postings,
+        true,
+        closing_account,
+    );
+    println!();
+    print_transaction(
+        opening_date,
+        "Opening balances",
+        &postings,
+        false,
+        opening_account,
+    );
+    Ok(())
+}
+
+/// The open lots of every account and commodity, the oldest ones are the first ones to be sold
+fn lots(options: &CommonOpts, ledger: &Ledger) -> Result<Lots, Box<dyn std::error::Error>> {
+    let mut regexes = HashMap::new();
+    let query = filter::preprocess_query(&options.query, &options.related)?;
+    let node = if query.len() > 2 {
+        Some(build_root_node_from_expression(
+            query.as_str(),
+            &mut regexes,
+        ))
+    } else {
+        None
+    };
+
+    let mut lots: Lots = HashMap::new();
+    for t in ledger.transactions.iter() {
+        for p in t.postings.borrow().iter() {
+            if !filter::filter(options, &node, t, p, &ledger.commodities)? {
+                continue;
+            }
+            let (mut amount, commodity) = match p.amount.as_ref().unwrap() {
+                Money::Zero => continue,
+                Money::Money { amount, currency } => (amount.clone(), currency.clone()),
+            };
+            let unit_cost = match &p.cost {
+                None => None,
+                Some(Cost::PerUnit { amount }) => Some(amount.clone()),
+                Some(Cost::Total { amount: total }) => Some(total.clone() / amount.abs()),
+            };
+            let account_lots = lots
+                .entry((
+                    p.account.get_name().to_string(),
+                    commodity.get_name().to_string(),
+                ))
+                .or_default();
+            // Close the existing lots in the opposite direction first
+            while !amount.is_zero()
+                & matches!(account_lots.first(), Some(lot) if lot.0.is_positive() != amount.is_positive())
+            {
+                let lot = &mut account_lots[0];
+                if lot.0.abs() > amount.abs() {
+                    lot.0 = lot.0.clone() + amount;
+                    amount = BigRational::zero();
+                } else {
+                    amount += lot.0.clone();
+                    account_lots.remove(0);
+                }
+            }
+            if !amount.is_zero() {
+                account_lots.push((amount, unit_cost));
+            }
+        }
+    }
+    Ok(lots)
+}
+
+/// Prints a transaction as journal text, the equity posting is left empty so that it takes whatever balances it
+fn print_transaction(
+    date: NaiveDate,
+    description: &str,
+    postings: &[(String, Money, Option<Money>)],
+    negate: bool,
+    equity_account: &str,
+) {
+    println!("{} {}", date, description);
+    for (account, amount, cost) in postings.iter() {
+        let amount = match negate {
+            true => -amount.clone(),
+            false => amount.clone(),
+        };
+        match cost {
+            Some(cost) => println!(
+                "    {:<48}  {} {{={}}}",
+                account,
+                amount.to_journal_string(),
+                cost.to_journal_string()
+            ),
+            None => println!("    {:<48}  {}", account, amount.to_journal_string()),
+        }
+    }
+    println!("    {}", equity_account);
+}
@@ -0,0 +1,247 @@
+//! Report templates for the `--format`, `--register-format` and `--balance-format` options
+//!
+//! A template is text with fields like `%(date)`, `%-20(payee)` or `%12.12(amount)`:
+//! - a `-` aligns the field to the left, otherwise it is aligned to the right
+//! - the first number is the minimum width and the one after the dot the maximum width
+//! - the field is either a name provided by the report or a value expression, like `%(abs(amount))`
+//!
+//! `\n` and `\t` are a new line and a tab, and `%%` is a percentage sign.
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use num::ToPrimitive;
+use pest::Parser;
+use regex::Regex;
+
+use crate::error::InvalidFormatError;
+use crate::models::{Currency, HasName, Posting, Transaction};
+use crate::parser::value_expr::{build_root_node_from_expression, eval, EvalResult, Node};
+use crate::parser::{GrammarParser, Rule};
+use crate::List;
+
+#[derive(Debug, Clone)]
+enum Element {
+    Text(String),
+    Field {
+        left_align: bool,
+        min_width: Option<usize>,
+        max_width: Option<usize>,
+        name: String,
+        /// The value expression of the field, if it is a valid one
+        node: Option<Node>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Template {
+    elements: Vec<Element>,
+    /// The regexes of the value expressions, compiled when parsing the template
+    regexes: RefCell<HashMap<String, Regex>>,
+}
+
+/// What a report line is about, for the fields that are value expressions
+pub(crate) struct Context<'a> {
+    pub(crate) posting: Option<(&'a Posting, &'a Transaction<Posting>)>,
+    pub(crate) commodities: &'a List<Currency>,
+    pub(crate) date_format: &'a str,
+}
+
+impl Template {
+    pub(crate) fn parse(template: &str) -> Result<Template, Box<dyn std::error::Error>> {
+        let mut elements = vec![];
+        let mut regexes = HashMap::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some(other) => text.push(other),
+                    None => text.push('\\'),
+                },
+                '%' if chars.peek() == Some(&'%') => {
+                    chars.next();
+                    text.push('%');
+                }
+                '%' => {
+                    if !text.is_empty() {
+                        elements.push(Element::Text(text.clone()));
+                        text.clear();
+                    }
+                    let left_align = chars.peek() == Some(&'-');
+                    if left_align {
+                        chars.next();
+                    }
+                    let mut min_width = String::new();
+                    while let Some(d) = chars.peek().filter(|x| x.is_ascii_digit()) {
+                        min_width.push(*d);
+                        chars.next();
+                    }
+                    let mut max_width = String::new();
+                    if chars.peek() == Some(&'.') {
+                        chars.next();
+                        while let Some(d) = chars.peek().filter(|x| x.is_ascii_digit()) {
+                            max_width.push(*d);
+                            chars.next();
+                        }
+                    }
+                    if chars.next() != Some('(') {
+                        return Err(Box::new(InvalidFormatError(template.to_string())));
+                    }
+                    // The field ends with the matching parenthesis
+                    let mut depth = 1;
+                    let mut name = String::new();
+                    for x in chars.by_ref() {
+                        match x {
+                            '(' => depth += 1,
+                            ')' => depth -= 1,
+                            _ => {}
+                        }
+                        if depth == 0 {
+                            break;
+                        }
+                        name.push(x);
+                    }
+                    if depth != 0 {
+                        return Err(Box::new(InvalidFormatError(template.to_string())));
+                    }
+                    let name = name.trim().to_string();
+                    let expression = format!("({})", name);
+                    let node = match GrammarParser::parse(Rule::value_expr, expression.as_str()) {
+                        Ok(_) => Some(build_root_node_from_expression(
+                            expression.as_str(),
+                            &mut regexes,
+                        )),
+                        Err(_) => None,
+                    };
+                    elements.push(Element::Field {
+                        left_align,
+                        min_width: min_width.parse().ok(),
+                        max_width: max_width.parse().ok(),
+                        name,
+                        node,
+                    });
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            elements.push(Element::Text(text));
+        }
+        Ok(Template {
+            elements,
+            regexes: RefCell::new(regexes),
+        })
+    }
+
+    /// Checks that the report provides every field, for reports whose lines are not about a posting
+    pub(crate) fn check_fields(&self, names: &[&str]) -> Result<(), InvalidFormatError> {
+        for element in self.elements.iter() {
+            if let Element::Field { name, .. } = element {
+                if !names.contains(&name.as_str()) {
+                    return Err(InvalidFormatError(format!(
+                        "%({}), the fields are {}",
+                        name,
+                        names.join(", ")
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills the template with the values provided by the report, or by evaluating the fields
+    ///
+    /// The result always ends in a new line.
+    pub(crate) fn render(
+        &self,
+        values: &HashMap<&str, String>,
+        context: &Context,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut line = String::new();
+        for element in self.elements.iter() {
+            match element {
+                Element::Text(text) => line.push_str(text),
+                Element::Field {
+                    left_align,
+                    min_width,
+                    max_width,
+                    name,
+                    node,
+                } => {
+                    let mut value = match (values.get(name.as_str()), context.posting, node) {
+                        (Some(value), _, _) => value.clone(),
+                        (None, Some((posting, transaction)), Some(node)) => {
+                            let result = eval(
+                                node,
+                                posting,
+                                transaction,
+                                context.commodities,
+                                &mut self.regexes.borrow_mut(),
+                            )?;
+                            eval_result_to_string(result, context.date_format)
+                        }
+                        _ => return Err(Box::new(InvalidFormatError(format!("%({})", name)))),
+                    };
+                    if let Some(max) = max_width {
+                        value = value.chars().take(*max).collect();
+                    }
+                    let width = min_width.unwrap_or(0);
+                    match left_align {
+                        true => line.push_str(&format!("{:<width$}", value, width = width)),
+                        false => line.push_str(&format!("{:>width$}", value, width = width)),
+                    }
+                }
+            }
+        }
+        if !line.ends_with('\n') {
+            line.push('\n');
+        }
+        Ok(line)
+    }
+}
+
+fn eval_result_to_string(result: EvalResult, date_format: &str) -> String {
+    match result {
+        EvalResult::Number(n) => format!("{}", n.to_f64().unwrap()),
+        EvalResult::Money(money) => format!("{}", money),
+        EvalResult::Boolean(b) => format!("{}", b),
+        EvalResult::Account(account) => account.get_name().to_string(),
+        EvalResult::Payee(payee) => payee.get_name().to_string(),
+        EvalResult::String(string) => string.unwrap_or_default(),
+        EvalResult::Date(date) => format!("{}", date.format(date_format)),
+        EvalResult::Regex(_) | EvalResult::Note => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_render() {
+        let template = Template::parse("%(date) %-10.6(payee)|%8(amount)\\n").unwrap();
+        let commodities = List::new();
+        let context = Context {
+            posting: None,
+            commodities: &commodities,
+            date_format: "%Y-%m-%d",
+        };
+        let mut values = HashMap::new();
+        values.insert("date", "2021-01-01".to_string());
+        values.insert("payee", "Supermarket".to_string());
+        values.insert("amount", "10 EUR".to_string());
+        assert_eq!(
+            template.render(&values, &context).unwrap(),
+            "2021-01-01 Superm    |  10 EUR\n"
+        );
+
+        // Without a posting, only the provided values are available
+        let template = Template::parse("%(abs(amount)) 100%%").unwrap();
+        assert!(template.render(&values, &context).is_err());
+        assert!(template.check_fields(&["amount"]).is_err());
+        assert!(Template::parse("%(date").is_err());
+        assert!(Template::parse("%date").is_err());
+    }
+}
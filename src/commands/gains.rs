@@ -0,0 +1,338 @@
+//! Capital gains report
+//!
+//! Postings with a cost are purchases (positive quantity) or disposals (negative quantity) of a
+//! commodity. A sale without a cost is priced from the other commodity of its transaction. Every
+//! disposal is matched with the lots it comes from, first in first out (lots given on the sale
+//! are ignored), and its cost basis is either the cost of those lots or the average cost of everything held. The report
+//! is written as CSV, one line for every lot a disposal comes from.
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+
+use chrono::NaiveDate;
+use num::{BigRational, Signed, Zero};
+
+use crate::error::GainsError;
+use crate::models::{
+    plain_decimal, Balance, Cost, Currency, HasName, Ledger, Money, Posting, PostingType,
+    Transaction,
+};
+use crate::parser::value_expr::build_root_node_from_expression;
+use crate::{filter, CommonOpts};
+use std::rc::Rc;
+
+/// Shares bought at the same time and price
+#[derive(Debug, Clone)]
+struct Lot {
+    date: NaiveDate,
+    quantity: BigRational,
+    unit_cost: BigRational,
+}
+
+/// The part of a disposal that comes from a lot
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Disposal {
+    acquired: NaiveDate,
+    disposed: NaiveDate,
+    quantity: BigRational,
+    basis: BigRational,
+    proceeds: BigRational,
+}
+
+/// The lots held of a commodity, the oldest first
+#[derive(Debug, Default)]
+struct Holding {
+    lots: VecDeque<Lot>,
+    currency: Option<Rc<Currency>>,
+}
+
+impl Holding {
+    fn buy(&mut self, date: NaiveDate, quantity: BigRational, unit_cost: BigRational) {
+        self.lots.push_back(Lot {
+            date,
+            quantity,
+            unit_cost,
+        });
+    }
+
+    /// Takes `quantity` out of the oldest lots, `None` if there is not that much held
+    fn sell(
+        &mut self,
+        date: NaiveDate,
+        quantity: &BigRational,
+        unit_price: &BigRational,
+        average_cost: bool,
+    ) -> Option<Vec<Disposal>> {
+        let held: BigRational = self.lots.iter().map(|x| x.quantity.clone()).sum();
+        if &held < quantity {
+            return None;
+        }
+        let average = match average_cost {
+            true => {
+                let cost: BigRational = self.lots.iter().map(|x| &x.quantity * &x.unit_cost).sum();
+                Some(cost / held)
+            }
+            false => None,
+        };
+        let mut disposals = vec![];
+        let mut left = quantity.clone();
+        while left.is_positive() {
+            let lot = self.lots.front_mut().unwrap();
+            let taken = left.clone().min(lot.quantity.clone());
+            let unit_cost = average.as_ref().unwrap_or(&lot.unit_cost);
+            disposals.push(Disposal {
+                acquired: lot.date,
+                disposed: date,
+                quantity: taken.clone(),
+                basis: &taken * unit_cost,
+                proceeds: &taken * unit_price,
+            });
+            lot.quantity -= &taken;
+            left -= &taken;
+            if lot.quantity.is_zero() {
+                self.lots.pop_front();
+            }
+        }
+        // With average cost, what is left is a single pool at the same average
+        if let Some(average) = average {
+            for lot in self.lots.iter_mut() {
+                lot.unit_cost = average.clone();
+            }
+        }
+        Some(disposals)
+    }
+}
+
+pub fn execute(
+    options: &CommonOpts,
+    maybe_ledger: Option<Ledger>,
+    average_cost: bool,
+    holding_period: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = match maybe_ledger {
+        Some(ledger) => ledger,
+        None => Ledger::try_from(options)?,
+    };
+
+    let mut regexes = HashMap::new();
+    let query = filter::preprocess_query(&options.query, &options.related)?;
+    let node = if query.len() > 2 {
+        Some(build_root_node_from_expression(
+            query.as_str(),
+            &mut regexes,
+        ))
+    } else {
+        None
+    };
+
+    // Purchases before the period are needed for the disposals within it
+    let mut unbounded = options.clone();
+    unbounded.begin = None;
+    unbounded.end = None;
+    unbounded.period = None;
+    let begin = [options.begin, options.period.map(|x| x.0)]
+        .iter()
+        .flatten()
+        .max()
+        .copied();
+    let end = [options.end, options.period.map(|x| x.1)]
+        .iter()
+        .flatten()
+        .min()
+        .copied();
+
+    let mut holdings: HashMap<Rc<Currency>, Holding> = HashMap::new();
+    // (commodity, disposal)
+    let mut disposals: Vec<(Rc<Currency>, Disposal)> = vec![];
+    for t in ledger.transactions.iter() {
+        for p in t.postings.borrow().iter() {
+            let amount = match &p.amount {
+                Some(amount @ Money::Money { .. }) => amount,
+                _ => continue,
+            };
+            if matches!(end, Some(end) if p.date >= end) {
+                continue;
+            }
+            if !filter::filter(&unbounded, &node, t, p, &ledger.commodities)? {
+                continue;
+            }
+            let commodity = amount.get_commodity().unwrap();
+            let quantity = amount.get_amount();
+            // The lot of a sale is not its price, the lots sold are the oldest ones anyway
+            let cost = match &p.cost {
+                Some(Cost::PerUnit { amount })
+                    if quantity.is_negative() & (p.lot_price.as_ref() == Some(amount)) =>
+                {
+                    None
+                }
+                cost => cost.clone(),
+            };
+            let (price_currency, unit_price) = match cost {
+                Some(Cost::PerUnit { amount }) => (amount.get_commodity(), amount.get_amount()),
+                Some(Cost::Total { amount }) => (
+                    amount.get_commodity(),
+                    amount.get_amount().abs() / quantity.abs(),
+                ),
+                // Sales of what is held get their price from the rest of the transaction
+                None => {
+                    let held = holdings.get(&commodity).is_some_and(|x| !x.lots.is_empty());
+                    if !quantity.is_negative() | !held {
+                        continue;
+                    }
+                    match implied_price(t, &commodity) {
+                        Ok(Some(price)) => (price.get_commodity(), price.get_amount()),
+                        // Moved from one account to another
+                        Ok(None) => continue,
+                        Err(()) => {
+                            return Err(Box::new(GainsError::NoPrice(
+                                commodity.get_name().to_string(),
+                                p.account.get_name().to_string(),
+                                p.date,
+                            )))
+                        }
+                    }
+                }
+            };
+            let price_currency = match price_currency {
+                Some(currency) => currency,
+                None => continue,
+            };
+            let holding = holdings.entry(commodity.clone()).or_default();
+            match &holding.currency {
+                Some(currency) if currency != &price_currency => {
+                    return Err(Box::new(GainsError::DifferentCurrencies(
+                        commodity.get_name().to_string(),
+                    )))
+                }
+                _ => holding.currency = Some(price_currency),
+            }
+            if quantity.is_positive() {
+                holding.buy(p.date, quantity, unit_price);
+                continue;
+            }
+            let sold = holding
+                .sell(p.date, &quantity.abs(), &unit_price, average_cost)
+                .ok_or_else(|| {
+                    GainsError::NotEnoughHeld(commodity.get_name().to_string(), p.date)
+                })?;
+            if matches!(begin, Some(begin) if p.date < begin) {
+                continue;
+            }
+            for disposal in sold {
+                disposals.push((commodity.clone(), disposal));
+            }
+        }
+    }
+
+    println!("Commodity,Acquired,Disposed,Quantity,Currency,Cost basis,Proceeds,Gain,Term");
+    for (commodity, disposal) in disposals.iter() {
+        let currency = holdings[commodity].currency.clone().unwrap();
+        let decimals = currency.get_precision();
+        let term = match (disposal.disposed - disposal.acquired).num_days() > holding_period {
+            true => "long",
+            false => "short",
+        };
+        println!(
+            "{},{},{},{},{},{},{},{},{}",
+            csv_field(commodity.get_name()),
+            disposal.acquired.format("%Y-%m-%d"),
+            disposal.disposed.format("%Y-%m-%d"),
+            plain_decimal(&disposal.quantity, commodity.get_precision()),
+            csv_field(currency.get_name()),
+            plain_decimal(&disposal.basis, decimals),
+            plain_decimal(&disposal.proceeds, decimals),
+            plain_decimal(&(&disposal.proceeds - &disposal.basis), decimals),
+            term
+        );
+    }
+    Ok(())
+}
+
+/// The unit price of a commodity exchanged for a single other one in a transaction
+///
+/// It is `None` when nothing else is exchanged, and an error when there is more than one other
+/// commodity.
+fn implied_price(t: &Transaction<Posting>, commodity: &Rc<Currency>) -> Result<Option<Money>, ()> {
+    let mut quantity = BigRational::zero();
+    let mut others = Balance::new();
+    for p in t.postings.borrow().iter() {
+        if p.kind != PostingType::Real {
+            continue;
+        }
+        match &p.amount {
+            Some(Money::Money { currency, amount }) if currency == commodity => quantity += amount,
+            Some(money) => others = others + Balance::from(money.clone()),
+            None => {}
+        }
+    }
+    if others.is_zero() {
+        return Ok(None);
+    }
+    match others.to_money() {
+        Ok(Money::Money { currency, amount }) if !quantity.is_zero() => {
+            Ok(Some(Money::from((currency, (amount / quantity).abs()))))
+        }
+        _ => Err(()),
+    }
+}
+
+/// Quotes the field if it has a comma or quotes
+fn csv_field(field: &str) -> String {
+    match field.contains(|c| (c == ',') | (c == '"')) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigInt;
+
+    fn date(m: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, m, 1).unwrap()
+    }
+    fn n(x: i64) -> BigRational {
+        BigRational::from_integer(BigInt::from(x))
+    }
+
+    fn holding() -> Holding {
+        let mut holding = Holding::default();
+        holding.buy(date(1), n(10), n(100));
+        holding.buy(date(2), n(10), n(200));
+        holding
+    }
+
+    #[test]
+    fn fifo() {
+        let mut holding = holding();
+        let sold = holding.sell(date(3), &n(15), &n(300), false).unwrap();
+        assert_eq!(sold.len(), 2);
+        assert_eq!((sold[0].acquired, &sold[0].basis), (date(1), &n(1000)));
+        assert_eq!((sold[1].acquired, &sold[1].basis), (date(2), &n(1000)));
+        assert_eq!(sold[1].proceeds, n(1500));
+        assert!(holding.sell(date(4), &n(6), &n(300), false).is_none());
+    }
+
+    #[test]
+    fn average_cost() {
+        let mut holding = holding();
+        let sold = holding.sell(date(3), &n(15), &n(300), true).unwrap();
+        assert_eq!(sold[0].basis, n(1500));
+        assert_eq!(sold[1].basis, n(750));
+        let sold = holding.sell(date(4), &n(5), &n(300), true).unwrap();
+        assert_eq!(sold[0].basis, n(750));
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(plain_decimal(&(n(1) / n(3)), 2), "0.33");
+    }
+
+    #[test]
+    fn large_amounts() {
+        // Too many digits for a float
+        let mut holding = Holding::default();
+        let cost = n(12_345_678_901_234_568) / n(100);
+        holding.buy(date(1), n(1), cost.clone());
+        let sold = holding.sell(date(2), &n(1), &(cost * n(2)), false).unwrap();
+        assert_eq!(plain_decimal(&sold[0].basis, 2), "123456789012345.68");
+        assert_eq!(plain_decimal(&sold[0].proceeds, 2), "246913578024691.36");
+    }
+}
@@ -0,0 +1,219 @@
+//! Loan amortization
+//!
+//! Every payment of a fixed rate loan is the same, and it is split into the interest of the
+//! outstanding debt and the principal that is paid back. The schedule is written as journal
+//! text, either to be added to the journal or, with virtual postings, as a forecast.
+use std::convert::TryFrom;
+
+use chrono::{Months, NaiveDate};
+use num::{BigInt, BigRational, One, Signed, Zero};
+use pest::Parser;
+
+use crate::commands::roi::Frequency;
+use crate::error::LoanError;
+use crate::models::{Ledger, Money};
+use crate::parser::utils::{count_decimals, parse_decimal, parse_money};
+use crate::parser::{GrammarParser, Rule};
+use crate::CommonOpts;
+
+/// The terms of the loan and where its payments go
+#[derive(Debug, Clone)]
+pub struct Loan {
+    /// The amount borrowed, like "200000 EUR"
+    pub principal: String,
+    /// The yearly interest rate as a percentage, like "3.5"
+    pub rate: String,
+    /// The number of payments
+    pub payments: u32,
+    /// The date of the first payment
+    pub start: NaiveDate,
+    pub frequency: Frequency,
+    pub payee: String,
+    pub loan_account: String,
+    pub interest_account: String,
+    pub payment_account: String,
+    /// The decimals of the amounts, instead of the rounding precision of the commodity
+    pub precision: Option<usize>,
+}
+
+/// One payment of the schedule
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Installment {
+    interest: BigRational,
+    principal: BigRational,
+}
+
+pub fn execute(
+    options: &CommonOpts,
+    maybe_ledger: Option<Ledger>,
+    loan: &Loan,
+    virtual_postings: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = match maybe_ledger {
+        Some(ledger) => ledger,
+        None => Ledger::try_from(options)?,
+    };
+    let (currency, principal) = match parse_money(&loan.principal, &ledger.commodities) {
+        Some(Money::Money { currency, amount }) if amount.is_positive() => (currency, amount),
+        _ => {
+            return Err(Box::new(LoanError::InvalidPrincipal(
+                loan.principal.clone(),
+            )))
+        }
+    };
+    let rate = match parse_rate(&loan.rate) {
+        Some(rate) => rate,
+        None => return Err(Box::new(LoanError::InvalidRate(loan.rate.clone()))),
+    };
+    if loan.payments == 0 {
+        return Err(Box::new(LoanError::NoPayments));
+    }
+
+    let (months, per_year) = match loan.frequency {
+        Frequency::Monthly => (1, 12),
+        Frequency::Quarterly => (3, 4),
+        Frequency::Yearly => (12, 1),
+    };
+    let precision = match loan.precision {
+        Some(precision) => precision,
+        None => currency.rounding_precision(count_decimals(loan.principal.trim(), '.')),
+    };
+    let schedule = amortization(
+        &principal,
+        &(rate / BigInt::from(per_year)),
+        loan.payments,
+        precision,
+    );
+    let account = |name: &str| match virtual_postings {
+        true => format!("[{}]", name),
+        false => name.to_string(),
+    };
+    // Whatever the display format of the commodity, the schedule can be read back as a journal,
+    // with all the decimals it was rounded to
+    currency.update_precision(precision);
+    let money =
+        |amount: &BigRational| Money::from((currency.clone(), amount.clone())).to_journal_string();
+    for (i, installment) in schedule.iter().enumerate() {
+        let date = loan
+            .start
+            .checked_add_months(Months::new(months * i as u32))
+            .unwrap();
+        let payment = &installment.interest + &installment.principal;
+        println!("{} {}", date.format("%Y-%m-%d"), loan.payee);
+        println!(
+            "    {:<40}{:>20}",
+            account(&loan.loan_account),
+            money(&installment.principal)
+        );
+        println!(
+            "    {:<40}{:>20}",
+            account(&loan.interest_account),
+            money(&installment.interest)
+        );
+        println!(
+            "    {:<40}{:>20}",
+            account(&loan.payment_account),
+            money(&-payment)
+        );
+        println!();
+    }
+    Ok(())
+}
+
+/// The yearly rate as a fraction, from a percentage like `3.5` or `3.5%`
+fn parse_rate(text: &str) -> Option<BigRational> {
+    let text = text.trim().trim_end_matches('%').trim_end();
+    match GrammarParser::parse(Rule::number, text) {
+        Ok(mut parsed) if parsed.as_str() == text => {
+            let rate = parse_decimal(parsed.next().unwrap(), '.') / BigInt::from(100);
+            match rate.is_negative() {
+                true => None,
+                false => Some(rate),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Rounds to the given number of decimals, halves away from zero
+fn round(amount: &BigRational, decimals: usize) -> BigRational {
+    let scale = BigRational::from_integer(BigInt::from(10).pow(decimals as u32));
+    (amount * &scale).round() / scale
+}
+
+/// The schedule of a loan with a fixed payment
+///
+/// The payment is `P r / (1 - (1 + r)^-n)`, rounded to the precision of the commodity. The
+/// interest of every period is rounded as well and the last payment clears what is left, so
+/// the principal of the installments adds up to exactly what was borrowed.
+fn amortization(
+    principal: &BigRational,
+    rate: &BigRational,
+    payments: u32,
+    decimals: usize,
+) -> Vec<Installment> {
+    let n = BigRational::from_integer(BigInt::from(payments));
+    let payment = match rate.is_zero() {
+        true => round(&(principal / n), decimals),
+        false => {
+            let growth = num::pow(BigRational::one() + rate, payments as usize);
+            round(
+                &(principal * rate * &growth / (&growth - BigRational::one())),
+                decimals,
+            )
+        }
+    };
+    let mut debt = principal.clone();
+    let mut schedule = vec![];
+    for i in 0..payments {
+        let interest = round(&(&debt * rate), decimals);
+        let principal = match i + 1 == payments {
+            true => debt.clone(),
+            false => (&payment - &interest).min(debt.clone()),
+        };
+        debt -= &principal;
+        schedule.push(Installment {
+            interest,
+            principal,
+        });
+    }
+    schedule
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cents(amount: i64) -> BigRational {
+        BigRational::new(BigInt::from(amount), BigInt::from(100))
+    }
+
+    #[test]
+    fn fixed_payments() {
+        // 1000 at 1% a month, for a year
+        let schedule = amortization(&cents(100_000), &cents(1), 12, 2);
+        assert_eq!(schedule.len(), 12);
+        assert_eq!(schedule[0].interest, cents(1000));
+        assert_eq!(schedule[0].principal, cents(7885));
+        let total: BigRational = schedule.iter().map(|x| x.principal.clone()).sum();
+        assert_eq!(total, cents(100_000));
+        // Every payment but the last one is the same
+        for installment in schedule[..11].iter() {
+            assert_eq!(&installment.interest + &installment.principal, cents(8885));
+        }
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(
+            parse_rate("3.5%"),
+            Some(BigRational::new(35.into(), 1000.into()))
+        );
+        assert_eq!(parse_rate("0"), Some(BigRational::zero()));
+        assert_eq!(parse_rate("-1"), None);
+        assert_eq!(parse_rate("abc"), None);
+        let schedule = amortization(&cents(100_000), &BigRational::zero(), 3, 2);
+        assert_eq!(schedule[0].principal, cents(33333));
+        assert_eq!(schedule[2].principal, cents(33334));
+    }
+}
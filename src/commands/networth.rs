@@ -0,0 +1,273 @@
+//! Net worth over time
+//!
+//! The balance of the query is sampled at the end of every period and drawn as a chart.
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+
+use chrono::NaiveDate;
+use num::ToPrimitive;
+use prettytable::format;
+use prettytable::{Cell, Row, Table};
+
+use crate::commands::balance::convert_balance;
+use crate::commands::roi::Frequency;
+use crate::commands::statements::{balance_cell, report_columns};
+use crate::error::MixedCommoditiesError;
+use crate::models::{conversion, Balance, HasName, Ledger, Money};
+use crate::parser::value_expr::build_root_node_from_expression;
+use crate::{filter, CommonOpts};
+
+/// Width of the chart, without the labels
+const CHART_WIDTH: usize = 60;
+/// Height of the line chart
+const CHART_HEIGHT: usize = 12;
+
+pub fn execute(
+    options: &CommonOpts,
+    maybe_ledger: Option<Ledger>,
+    frequency: Frequency,
+    by_account: bool,
+    bars: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = match maybe_ledger {
+        Some(ledger) => ledger,
+        None => Ledger::try_from(options)?,
+    };
+
+    let mut regexes = HashMap::new();
+    let query = filter::preprocess_query(&options.query, &options.related)?;
+    let node = if query.len() > 2 {
+        Some(build_root_node_from_expression(
+            query.as_str(),
+            &mut regexes,
+        ))
+    } else {
+        None
+    };
+
+    // The net worth at a date includes everything before it
+    let mut unbounded = options.clone();
+    unbounded.begin = None;
+    unbounded.end = None;
+    unbounded.period = None;
+    let begin = [options.begin, options.period.map(|x| x.0)]
+        .iter()
+        .flatten()
+        .max()
+        .copied();
+    let end = [options.end, options.period.map(|x| x.1)]
+        .iter()
+        .flatten()
+        .min()
+        .copied();
+
+    // (top level account, date, amount)
+    let mut postings: Vec<(String, NaiveDate, Money)> = vec![];
+    for t in ledger.transactions.iter() {
+        for p in t.postings.borrow().iter() {
+            if matches!(end, Some(end) if p.date >= end) {
+                continue;
+            }
+            if !filter::filter(&unbounded, &node, t, p, &ledger.commodities)? {
+                continue;
+            }
+            let account = match by_account {
+                true => p.account.get_name().split(':').next().unwrap().to_string(),
+                false => String::new(),
+            };
+            let money = match options.exchange.is_empty() {
+                true => p.amount.clone().unwrap(),
+                false => p.exchange_amount(),
+            };
+            postings.push((account, p.date, money));
+        }
+    }
+    if postings.is_empty() {
+        return Ok(());
+    }
+    let first = match begin {
+        Some(begin) => begin,
+        None => postings.iter().map(|x| x.1).min().unwrap(),
+    };
+    let last = match end {
+        Some(end) => end.pred_opt().unwrap(),
+        None => postings.iter().map(|x| x.1).max().unwrap(),
+    };
+    let samples: Vec<NaiveDate> = report_columns(first, last, Some(frequency))
+        .iter()
+        .map(|x| x.1)
+        .collect();
+
+    // Balances by top level account, one per sample
+    let mut balances: BTreeMap<String, Vec<Balance>> = BTreeMap::new();
+    for (account, date, money) in postings.iter() {
+        let account_balances = balances
+            .entry(account.clone())
+            .or_insert_with(|| vec![Balance::new(); samples.len()]);
+        for (i, sample) in samples.iter().enumerate() {
+            if date <= sample {
+                account_balances[i] = account_balances[i].clone() + Balance::from(money.clone());
+            }
+        }
+    }
+    if let Some(currency_string) = options.exchange.first() {
+        let currency = ledger.commodities.get(currency_string)?;
+        for (i, sample) in samples.iter().enumerate() {
+            let multipliers =
+                conversion(currency.clone(), sample.succ_opt().unwrap(), &ledger.prices);
+            for account_balances in balances.values_mut() {
+                account_balances[i] =
+                    convert_balance(&account_balances[i], &multipliers, currency)?;
+            }
+        }
+    }
+    let mut totals = vec![Balance::new(); samples.len()];
+    for account_balances in balances.values() {
+        for (i, balance) in account_balances.iter().enumerate() {
+            totals[i] = totals[i].clone() + balance.clone();
+        }
+    }
+
+    // The chart needs a single commodity, checked before printing anything
+    let mut currency = None;
+    let mut values = vec![];
+    for (total, sample) in totals.iter().zip(samples.iter()) {
+        let error = || {
+            MixedCommoditiesError(format!(
+                "The net worth on {}",
+                sample.format(&options.date_format)
+            ))
+        };
+        let money = total.to_money().map_err(|_| error())?;
+        if let Some(c) = money.get_commodity() {
+            if currency.as_ref().is_some_and(|x| x != &c) {
+                return Err(Box::new(error()));
+            }
+            currency = Some(c);
+        }
+        values.push(money.get_amount().to_f64().unwrap());
+    }
+
+    // The table
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    let mut titles = vec![Cell::new("Date")];
+    if by_account {
+        for account in balances.keys() {
+            titles.push(Cell::new(account).style_spec("r"));
+        }
+    }
+    titles.push(Cell::new("Net worth").style_spec("r"));
+    table.set_titles(Row::new(titles));
+    for (i, sample) in samples.iter().enumerate() {
+        let mut row = vec![Cell::new(&format!(
+            "{}",
+            sample.format(&options.date_format)
+        ))];
+        if by_account {
+            for account_balances in balances.values() {
+                row.push(balance_cell(&account_balances[i], false));
+            }
+        }
+        row.push(balance_cell(&totals[i], false).style_spec("rb"));
+        table.add_row(Row::new(row));
+    }
+    table.printstd();
+
+    let labels: Vec<String> = samples
+        .iter()
+        .map(|x| format!("{}", x.format(&options.date_format)))
+        .collect();
+    println!();
+    let chart = match bars {
+        true => bar_chart(&labels, &values, CHART_WIDTH),
+        false => line_chart(&values, CHART_WIDTH, CHART_HEIGHT),
+    };
+    for line in chart {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// One horizontal bar per sample, proportional to the largest absolute value
+fn bar_chart(labels: &[String], values: &[f64], width: usize) -> Vec<String> {
+    let max = values.iter().fold(0.0_f64, |acc, x| acc.max(x.abs()));
+    let label_width = labels.iter().map(|x| x.chars().count()).max().unwrap_or(0);
+    labels
+        .iter()
+        .zip(values.iter())
+        .map(|(label, value)| {
+            let length = match max > 0.0 {
+                true => (value.abs() / max * width as f64).round() as usize,
+                false => 0,
+            };
+            let bar = if *value < 0.0 { "-" } else { "#" }.repeat(length);
+            format!(
+                "{:<label_width$} |{} {:.2}",
+                label,
+                bar,
+                value,
+                label_width = label_width
+            )
+        })
+        .collect()
+}
+
+/// Points of the values from left to right, with the scale on the left
+fn line_chart(values: &[f64], width: usize, height: usize) -> Vec<String> {
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let step = (width / values.len().max(1)).clamp(1, 6);
+    let row_of = |value: f64| match max > min {
+        true => ((value - min) / (max - min) * (height - 1) as f64).round() as usize,
+        false => 0,
+    };
+    let mut grid = vec![vec![' '; values.len() * step]; height];
+    for (i, value) in values.iter().enumerate() {
+        let row = row_of(*value);
+        grid[row][i * step] = '*';
+        // Join the point with the previous one
+        if i > 0 {
+            let previous = row_of(values[i - 1]);
+            for (r, line) in grid.iter_mut().enumerate() {
+                if (r > row.min(previous)) & (r < row.max(previous)) {
+                    line[i * step - step / 2] = '|';
+                }
+            }
+        }
+    }
+    let labels = [format!("{:.2}", max), format!("{:.2}", min)];
+    let label_width = labels.iter().map(|x| x.len()).max().unwrap();
+    grid.iter()
+        .enumerate()
+        .rev()
+        .map(|(r, line)| {
+            let label = match r {
+                r if r == height - 1 => labels[0].as_str(),
+                0 => labels[1].as_str(),
+                _ => "",
+            };
+            let line: String = line.iter().collect();
+            format!(
+                "{:>label_width$} |{}",
+                label,
+                line.trim_end(),
+                label_width = label_width
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charts() {
+        let bars = bar_chart(&["a".to_string(), "bb".to_string()], &[5.0, -10.0], 4);
+        assert_eq!(bars, vec!["a  |## 5.00", "bb |---- -10.00"]);
+
+        let line = line_chart(&[0.0, 2.0, 1.0], 6, 3);
+        assert_eq!(line, vec!["2.00 |  *", "     | |  *", "0.00 |*"]);
+    }
+}
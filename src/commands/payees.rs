@@ -1,4 +1,5 @@
-use crate::models::Ledger;
+use crate::commands::report::{summary_posting, Rows};
+use crate::models::{Ledger, Money};
 use crate::{
     models::{HasName, Payee},
     CommonOpts,
@@ -18,7 +19,24 @@ pub fn execute(options: &CommonOpts, maybe_ledger: Option<Ledger>) -> Result<(),
         .map(|x| x.1.deref().to_owned())
         .collect::<Vec<Payee>>();
     payees.sort_by(|a, b| a.get_name().cmp(b.get_name()));
-    for payee in payees.iter() {
+
+    let rows = Rows::new(options)?;
+    let mut displayed = vec![];
+    for payee in payees.into_iter() {
+        let (p, t) = summary_posting("", Money::Zero, payee.get_name());
+        if rows.is_displayed(&p, &t, &ledger.commodities)? {
+            displayed.push(payee);
+        }
+    }
+    payees = displayed;
+    if rows.is_sorted() {
+        payees = rows.sort(
+            payees,
+            |payee| summary_posting("", Money::Zero, payee.get_name()),
+            &ledger.commodities,
+        )?;
+    }
+    for payee in rows.limit(payees).iter() {
         println!("{}", payee);
     }
     Ok(())
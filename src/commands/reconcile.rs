@@ -0,0 +1,245 @@
+//! Reconciliation of an account against a bank statement
+//!
+//! The uncleared postings of the account are listed and toggled until the cleared balance matches
+//! the balance of the statement. Then the toggled postings are marked with `*` in the journal.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use colored::Colorize;
+
+use crate::error::ReconcileError;
+use crate::models::{Balance, Cleared, HasName, Ledger, Money, SourceLocation};
+use crate::parser::utils::parse_money;
+use crate::parser::value_expr::build_root_node_from_expression;
+use crate::{filter, CommonOpts};
+
+/// An uncleared posting that can be toggled
+struct Candidate {
+    date: NaiveDate,
+    payee: String,
+    account: String,
+    amount: Money,
+    source: Option<SourceLocation>,
+    selected: bool,
+}
+
+pub fn execute(
+    options: &CommonOpts,
+    maybe_ledger: Option<Ledger>,
+    statement_balance: &str,
+    date: Option<NaiveDate>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = match maybe_ledger {
+        Some(ledger) => ledger,
+        None => Ledger::try_from(options)?,
+    };
+    let statement = match parse_money(statement_balance, &ledger.commodities) {
+        Some(money) => Balance::from(money),
+        None => {
+            return Err(Box::new(ReconcileError::InvalidStatementBalance(
+                statement_balance.to_string(),
+            )))
+        }
+    };
+
+    let mut regexes = HashMap::new();
+    let query = filter::preprocess_query(&options.query, &options.related)?;
+    let node = if query.len() > 2 {
+        Some(build_root_node_from_expression(
+            query.as_str(),
+            &mut regexes,
+        ))
+    } else {
+        None
+    };
+
+    let mut cleared = Balance::new();
+    let mut candidates = vec![];
+    for t in ledger.transactions.iter() {
+        for p in t.postings.borrow().iter() {
+            if let Some(date) = date {
+                if p.date > date {
+                    continue;
+                }
+            }
+            if !filter::filter(options, &node, t, p, &ledger.commodities)? {
+                continue;
+            }
+            let amount = p.amount.clone().unwrap();
+            if p.cleared == Cleared::Cleared {
+                cleared = cleared + Balance::from(amount);
+                continue;
+            }
+            candidates.push(Candidate {
+                date: p.date,
+                payee: p.payee.as_ref().map_or("", |x| x.get_name()).to_string(),
+                account: p.account.get_name().to_string(),
+                amount,
+                source: p.source.clone(),
+                selected: false,
+            });
+        }
+    }
+    if candidates.is_empty() {
+        println!("There are no uncleared postings.");
+        return Ok(());
+    }
+
+    let mut rl = rustyline::Editor::<()>::new();
+    loop {
+        let difference = difference(&statement, &cleared, &candidates);
+        print_candidates(&candidates, &difference, &options.date_format);
+        if difference.is_zero() & candidates.iter().any(|x| x.selected) {
+            let answer =
+                match rl.readline("The difference is zero, mark the postings as cleared? (y/n) ") {
+                    Ok(answer) => answer,
+                    Err(_) => break,
+                };
+            if answer.trim().eq_ignore_ascii_case("y") {
+                let count = mark_cleared(&candidates)?;
+                println!("{} postings marked as cleared.", count);
+                break;
+            }
+        }
+        let line = match rl.readline("Toggle (numbers or ranges like 2-5, all, none, quit): ") {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        match line.trim() {
+            "quit" | "exit" | "q" => break,
+            "all" => candidates.iter_mut().for_each(|x| x.selected = true),
+            "none" => candidates.iter_mut().for_each(|x| x.selected = false),
+            other => {
+                for index in parse_selection(other, candidates.len()) {
+                    match index {
+                        Some(i) => candidates[i].selected = !candidates[i].selected,
+                        None => eprintln!("Not a posting: {}", other),
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What is left to reconcile after the cleared and the selected postings
+fn difference(statement: &Balance, cleared: &Balance, candidates: &[Candidate]) -> Balance {
+    let mut difference = statement.clone() - cleared.clone();
+    for candidate in candidates.iter().filter(|x| x.selected) {
+        difference = difference - Balance::from(candidate.amount.clone());
+    }
+    difference
+}
+
+fn print_candidates(candidates: &[Candidate], difference: &Balance, date_format: &str) {
+    for (i, candidate) in candidates.iter().enumerate() {
+        println!(
+            "{:>4} [{}] {} {:<30} {:<30} {:>15}",
+            i + 1,
+            if candidate.selected { "x" } else { " " },
+            candidate.date.format(date_format),
+            candidate.payee.chars().take(30).collect::<String>(),
+            candidate.account.chars().take(30).collect::<String>(),
+            format!("{}", candidate.amount)
+        );
+    }
+    let difference = match difference.is_zero() {
+        true => "0".green(),
+        false => format!("{}", difference).red(),
+    };
+    println!("Difference: {}", difference);
+}
+
+/// The (zero based) indices of a selection like `1 3 5-7`, `None` for the ones out of range
+fn parse_selection(text: &str, count: usize) -> Vec<Option<usize>> {
+    let mut indices = vec![];
+    for part in text.split(|c: char| c.is_whitespace() | (c == ',')) {
+        if part.is_empty() {
+            continue;
+        }
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first.parse::<usize>(), last.parse::<usize>()),
+            None => (part.parse::<usize>(), part.parse::<usize>()),
+        };
+        match (first, last) {
+            (Ok(first), Ok(last)) if (first >= 1) & (first <= last) & (last <= count) => {
+                indices.extend((first..=last).map(|x| Some(x - 1)))
+            }
+            _ => indices.push(None),
+        }
+    }
+    indices
+}
+
+/// Writes the `*` status marker of the selected postings in the journal files
+fn mark_cleared(candidates: &[Candidate]) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut lines: BTreeMap<PathBuf, HashSet<usize>> = BTreeMap::new();
+    for candidate in candidates.iter().filter(|x| x.selected) {
+        match &candidate.source {
+            Some(source) if source.file.as_os_str() != "-" => {
+                lines
+                    .entry(source.file.clone())
+                    .or_default()
+                    .insert(source.line);
+            }
+            _ => {
+                return Err(Box::new(ReconcileError::UnknownLocation(
+                    candidate.account.clone(),
+                )))
+            }
+        }
+    }
+    let mut count = 0;
+    for (file, numbers) in lines.iter() {
+        let content = fs::read_to_string(file)?;
+        let mut new_content = String::new();
+        for (i, line) in content.split_inclusive('\n').enumerate() {
+            match numbers.contains(&(i + 1)) {
+                true => {
+                    new_content.push_str(&cleared_line(line));
+                    count += 1;
+                }
+                false => new_content.push_str(line),
+            }
+        }
+        fs::write(file, new_content)?;
+    }
+    Ok(count)
+}
+
+/// The posting line with a `*` status, replacing the `!` if there is one
+fn cleared_line(line: &str) -> String {
+    let content = line.trim_start();
+    let indent = &line[..line.len() - content.len()];
+    let rest = match content.strip_prefix(|c| (c == '!') | (c == '*')) {
+        Some(rest) => rest.trim_start(),
+        None => content,
+    };
+    format!("{}* {}", indent, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selection() {
+        assert_eq!(
+            parse_selection("1 3-4,6", 6),
+            vec![Some(0), Some(2), Some(3), Some(5)]
+        );
+        assert_eq!(parse_selection("7 0 a", 6), vec![None, None, None]);
+    }
+
+    #[test]
+    fn status_marker() {
+        assert_eq!(
+            cleared_line("    Assets:Bank    20 EUR\n"),
+            "    * Assets:Bank    20 EUR\n"
+        );
+        assert_eq!(cleared_line("\t!  Assets:Bank\n"), "\t* Assets:Bank\n");
+    }
+}
@@ -1,18 +1,28 @@
-use crate::models::{conversion, Cleared, HasName, Ledger, Posting, PostingType};
+use crate::commands::format::{Context, Template};
+use crate::commands::report::Rows;
+use crate::models::{
+    conversion, Cleared, HasName, Ledger, Posting, PostingOrigin, PostingType, Transaction,
+};
 use crate::models::{Balance, Money};
 use crate::parser::value_expr::build_root_node_from_expression;
 use crate::{filter, CommonOpts};
 use chrono::Utc;
 use colored::Colorize;
+use num::{BigInt, BigRational};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::rc::Rc;
 use terminal_size::{terminal_size, Width};
 
 /// Register report
+///
+/// With `average` the total column is the running mean of the postings instead of the running
+/// total, and with `deviation` the amount column is how far every posting is from that mean.
 pub fn execute(
     options: &CommonOpts,
     maybe_ledger: Option<Ledger>,
+    average: bool,
+    deviation: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Get options from options
     let _no_balance_check: bool = options.no_balance_check;
@@ -41,9 +51,14 @@ pub fn execute(
         width - w_date - w_description - w_amount - w_balance
     };
 
+    let template = match options.register_format.as_ref().or(options.format.as_ref()) {
+        Some(format) => Some(Template::parse(format)?),
+        None => None,
+    };
+
     // Build a cache of abstract value trees, it takes time to parse expressions, so better do it only once
     let mut regexes = HashMap::new();
-    let query = filter::preprocess_query(&options.query, &options.related);
+    let query = filter::preprocess_query(&options.query, &options.related)?;
     let node = if query.len() > 2 {
         Some(build_root_node_from_expression(
             query.as_str(),
@@ -53,8 +68,10 @@ pub fn execute(
         None
     };
 
+    // The postings of the report, grouped by transaction
+    let rows = Rows::new(options)?;
+    let mut groups: Vec<(&Transaction<Posting>, Vec<Posting>)> = vec![];
     for t in ledger.transactions.iter() {
-        let mut counter = 0;
         let mut postings_vec = t
             .postings
             .borrow()
@@ -65,18 +82,24 @@ pub fn execute(
             .cloned()
             .collect::<Vec<Posting>>();
 
+        // Report the value of the tag instead of the account
+        if let Some(tag) = &options.pivot {
+            for p in postings_vec.iter_mut() {
+                p.account = p.get_pivot_account(tag);
+            }
+        }
+
         // If the exchange option is active, change the amount of every posting to the desired currency. The balance will follow.
-        if let Some(currency_string) = &options.exchange {
+        if let Some(currency_string) = options.exchange.first() {
             if let Ok(currency) = ledger.commodities.get(currency_string) {
                 // for index in 0..postings_vec.len() {
                 for p in postings_vec.iter_mut() {
                     // let p = &postings_vec[index];
                     let multipliers = conversion(currency.clone(), p.date, &ledger.prices);
-                    if let Some(mult) = multipliers
-                        .get(p.amount.as_ref().unwrap().get_commodity().unwrap().as_ref())
-                    {
+                    let amount = p.exchange_amount();
+                    if let Some(mult) = multipliers.get(amount.get_commodity().unwrap().as_ref()) {
                         let new_amount = Money::Money {
-                            amount: p.amount.as_ref().unwrap().get_amount() * mult.clone(),
+                            amount: amount.get_amount() * mult.clone(),
                             currency: Rc::new(currency.as_ref().clone()),
                         };
                         let mut new_posting = p.clone();
@@ -137,8 +160,102 @@ pub fn execute(
             }
             postings_vec = collapsed;
         }
-        for p in postings_vec.iter() {
+        groups.push((t, postings_vec));
+    }
+
+    // Sorting breaks the transactions apart, so every posting is shown with its date and payee
+    if rows.is_sorted() {
+        let mut sorted = vec![];
+        for (t, postings_vec) in groups.into_iter() {
+            for p in postings_vec.into_iter() {
+                let key = rows.sort_key(&p, t, &ledger.commodities)?;
+                sorted.push((key, t, p));
+            }
+        }
+        sorted.sort_by(|a, b| a.0.cmp(&b.0));
+        groups = sorted.into_iter().map(|(_, t, p)| (t, vec![p])).collect();
+    }
+
+    // The hidden postings still count for the running total
+    let displayed: Vec<Vec<bool>> = groups
+        .iter()
+        .map(|(t, postings_vec)| {
+            postings_vec
+                .iter()
+                .map(|p| rows.is_displayed(p, t, &ledger.commodities))
+                .collect()
+        })
+        .collect::<Result<_, _>>()?;
+    let num_rows = displayed.iter().flatten().filter(|x| **x).count();
+    let mut row = 0;
+    // The number of postings in the running total
+    let mut count: u64 = 0;
+
+    for ((t, postings_vec), displayed) in groups.iter().zip(displayed.iter()) {
+        let mut counter = 0;
+        for (p, is_displayed) in postings_vec.iter().zip(displayed.iter()) {
+            balance = balance + Balance::from(p.amount.as_ref().unwrap().clone());
+            if balance.is_zero() {
+                balance = Balance::from(Money::Zero);
+            }
+            count += 1;
+            if !is_displayed {
+                continue;
+            }
+            row += 1;
+            if !rows.is_within_limits(row - 1, num_rows) {
+                continue;
+            }
             counter += 1;
+            let mean = balance.clone() / BigRational::from_integer(BigInt::from(count));
+            let total = match average {
+                true => &mean,
+                false => &balance,
+            };
+            let amount = match deviation {
+                true => {
+                    let amount = p.amount.clone().unwrap();
+                    let mean = mean
+                        .balance
+                        .get(&amount.get_commodity())
+                        .cloned()
+                        .unwrap_or_default();
+                    (amount - mean).to_money()?
+                }
+                false => p.amount.clone().unwrap(),
+            };
+            if let Some(template) = &template {
+                let mut values = HashMap::new();
+                values.insert(
+                    "date",
+                    format!("{}", t.date.unwrap().format(&options.date_format)),
+                );
+                values.insert(
+                    "payee",
+                    match t.get_payee(&ledger.payees) {
+                        Some(payee) => payee.get_name().to_string(),
+                        None => t.description.clone(),
+                    },
+                );
+                values.insert("code", t.code.clone().unwrap_or_default());
+                values.insert("account", p.account.get_name().to_string());
+                values.insert("amount", format!("{}", amount));
+                values.insert(
+                    "total",
+                    total
+                        .iter()
+                        .map(|x| format!("{}", x.1))
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                );
+                let context = Context {
+                    posting: Some((p, t)),
+                    commodities: &ledger.commodities,
+                    date_format: &options.date_format,
+                };
+                print!("{}", template.render(&values, &context)?);
+                continue;
+            }
             if counter == 1 {
                 let mut date_str =
                     format!("{}", t.date.unwrap().format(&options.date_format)).normal();
@@ -152,9 +269,12 @@ pub fn execute(
                 }
                 .normal();
 
-                if t.cleared == Cleared::NotCleared {
+                if t.cleared == Cleared::Pending {
                     payee_str = payee_str.bold();
                 }
+                if p.origin == PostingOrigin::Periodic {
+                    payee_str = payee_str.italic();
+                }
 
                 print!(
                     "{:w1$}{:width$}",
@@ -167,10 +287,6 @@ pub fn execute(
             if counter > 1 {
                 print!("{:width$}", "", width = w_description + 11);
             }
-            balance = balance + Balance::from(p.amount.as_ref().unwrap().clone());
-            if balance.is_zero() {
-                balance = Balance::from(Money::Zero);
-            }
             match p.kind {
                 PostingType::Real => print!(
                     "{:width$}",
@@ -189,20 +305,12 @@ pub fn execute(
                 ),
             }
 
-            match p.amount.as_ref().unwrap().is_negative() {
-                false => print!(
-                    "{:>width$}",
-                    format!("{}", p.amount.as_ref().unwrap()),
-                    width = w_amount
-                ),
-                true => print!(
-                    "{:>width$}",
-                    format!("{}", p.amount.as_ref().unwrap()).red(),
-                    width = w_amount
-                ),
+            match amount.is_negative() {
+                false => print!("{:>width$}", format!("{}", amount), width = w_amount),
+                true => print!("{:>width$}", format!("{}", amount).red(), width = w_amount),
             }
             let mut more_than_one_line: bool = false;
-            for (_, money) in balance.iter() {
+            for (_, money) in total.iter() {
                 if more_than_one_line {
                     print!(
                         "{:width$}",
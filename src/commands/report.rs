@@ -0,0 +1,236 @@
+//! Options shared by the reports: `--sort`, `--display`, `--head` and `--tail`
+//!
+//! The expressions are value expressions evaluated on every row of the report. The reports whose
+//! rows are not postings (balance, accounts, payees) evaluate them on a [`summary_posting`].
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use chrono::{NaiveDate, Utc};
+use num::BigRational;
+use pest::Parser;
+
+use crate::error::{InvalidDisplayError, InvalidExpressionError, LedgerError};
+use crate::models::{
+    Account, Currency, HasName, Money, Payee, Posting, PostingOrigin, PostingType, Transaction,
+    TransactionType,
+};
+use crate::parser::value_expr::{build_root_node_from_expression, eval, EvalResult, Node};
+use crate::parser::{GrammarParser, Rule};
+use crate::{CommonOpts, List};
+
+/// The value rows are sorted by
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum SortKey {
+    Number(BigRational),
+    Date(NaiveDate),
+    Text(String),
+}
+
+impl From<EvalResult> for SortKey {
+    fn from(result: EvalResult) -> Self {
+        match result {
+            EvalResult::Number(n) => SortKey::Number(n),
+            EvalResult::Money(money) => SortKey::Number(money.get_amount()),
+            EvalResult::Boolean(b) => SortKey::Number(BigRational::from_integer((b as i32).into())),
+            EvalResult::Date(date) => SortKey::Date(date),
+            EvalResult::Account(account) => SortKey::Text(account.get_name().to_string()),
+            EvalResult::Payee(payee) => SortKey::Text(payee.get_name().to_string()),
+            EvalResult::String(string) => SortKey::Text(string.unwrap_or_default()),
+            EvalResult::Regex(_) | EvalResult::Note => SortKey::Text(String::new()),
+        }
+    }
+}
+
+/// Sorting, display filter and limits of a report
+pub(crate) struct Rows {
+    sort: Option<Node>,
+    /// The expression, for the error message, and its node
+    display: Option<(String, Node)>,
+    head: Option<usize>,
+    tail: Option<usize>,
+}
+
+impl Rows {
+    pub(crate) fn new(options: &CommonOpts) -> Result<Rows, InvalidExpressionError> {
+        let mut regexes = HashMap::new();
+        let mut build = |expression: &Option<String>| {
+            let expression = match expression {
+                Some(expression) => expression,
+                None => return Ok(None),
+            };
+            // The whole expression has to parse, not only its beginning
+            let wrapped = format!("({})", expression);
+            let parsed = GrammarParser::parse(Rule::value_expr, wrapped.as_str())
+                .ok()
+                .and_then(|mut x| x.next())
+                .map(|x| x.as_str().to_string());
+            match parsed == Some(wrapped.clone()) {
+                true => Ok(Some(build_root_node_from_expression(
+                    wrapped.as_str(),
+                    &mut regexes,
+                ))),
+                false => Err(InvalidExpressionError(expression.clone())),
+            }
+        };
+        Ok(Rows {
+            sort: build(&options.sort)?,
+            display: options.display.clone().zip(build(&options.display)?),
+            head: options.head,
+            tail: options.tail,
+        })
+    }
+
+    pub(crate) fn is_sorted(&self) -> bool {
+        self.sort.is_some()
+    }
+
+    pub(crate) fn is_filtered(&self) -> bool {
+        self.display.is_some()
+    }
+
+    /// The value of the sort expression for a posting, if there is one
+    pub(crate) fn sort_key(
+        &self,
+        posting: &Posting,
+        transaction: &Transaction<Posting>,
+        commodities: &List<Currency>,
+    ) -> Result<Option<SortKey>, LedgerError> {
+        match &self.sort {
+            None => Ok(None),
+            Some(node) => Ok(Some(SortKey::from(eval(
+                node,
+                posting,
+                transaction,
+                commodities,
+                &mut HashMap::new(),
+            )?))),
+        }
+    }
+
+    /// Sorts the rows of a report that are not postings, by the sort expression of their summary posting
+    pub(crate) fn sort<T>(
+        &self,
+        rows: Vec<T>,
+        summary: impl Fn(&T) -> (Posting, Transaction<Posting>),
+        commodities: &List<Currency>,
+    ) -> Result<Vec<T>, LedgerError> {
+        let mut keyed = vec![];
+        for row in rows.into_iter() {
+            let (p, t) = summary(&row);
+            keyed.push((self.sort_key(&p, &t, commodities)?, row));
+        }
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(keyed.into_iter().map(|x| x.1).collect())
+    }
+
+    /// Whether the posting passes the display filter
+    pub(crate) fn is_displayed(
+        &self,
+        posting: &Posting,
+        transaction: &Transaction<Posting>,
+        commodities: &List<Currency>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        match &self.display {
+            None => Ok(true),
+            Some((expression, node)) => {
+                match eval(node, posting, transaction, commodities, &mut HashMap::new())? {
+                    EvalResult::Boolean(b) => Ok(b),
+                    _ => Err(Box::new(InvalidDisplayError(expression.clone()))),
+                }
+            }
+        }
+    }
+
+    /// Whether the row with the given index is shown, out of `count` displayed rows
+    pub(crate) fn is_within_limits(&self, index: usize, count: usize) -> bool {
+        let count = match self.head {
+            Some(head) => head.min(count),
+            None => count,
+        };
+        let first = match self.tail {
+            Some(tail) => count - tail.min(count),
+            None => 0,
+        };
+        (index >= first) & (index < count)
+    }
+
+    /// Keeps the first rows with `--head` and the last ones with `--tail`
+    pub(crate) fn limit<T>(&self, rows: Vec<T>) -> Vec<T> {
+        let count = rows.len();
+        rows.into_iter()
+            .enumerate()
+            .filter(|(i, _)| self.is_within_limits(*i, count))
+            .map(|(_, x)| x)
+            .collect()
+    }
+}
+
+/// A posting that stands for a row of a report that is not a posting, like an account and its balance
+pub(crate) fn summary_posting(
+    account: &str,
+    amount: Money,
+    payee: &str,
+) -> (Posting, Transaction<Posting>) {
+    let mut posting = Posting::new(
+        &Rc::new(Account::from(account)),
+        PostingType::Real,
+        &Payee::from(payee),
+        PostingOrigin::FromTransaction,
+        Utc::now().naive_local().date(),
+    );
+    posting.set_amount(amount);
+    let mut transaction = Transaction::new(TransactionType::Real);
+    transaction.description = payee.to_string();
+    transaction.date = Some(posting.date);
+    (posting, transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    #[test]
+    fn head_and_tail() {
+        let options = CommonOpts::from_iter(["", "-f", "", "--head", "4", "--tail", "2"].iter());
+        let rows = Rows::new(&options).unwrap();
+        assert_eq!(rows.limit(vec![1, 2, 3, 4, 5, 6]), vec![3, 4]);
+        assert_eq!(rows.limit(vec![1]), vec![1]);
+    }
+
+    #[test]
+    fn sort_by_expression() {
+        let options = CommonOpts::from_iter(["", "-f", "", "--sort", "-amount"].iter());
+        let rows = Rows::new(&options).unwrap();
+        let commodities = List::new();
+        let eur = Rc::new(Currency::from("EUR"));
+        let keys: Vec<SortKey> = [1, 3, 2]
+            .iter()
+            .map(|x| {
+                let amount = Money::from((eur.clone(), BigRational::from_integer((*x).into())));
+                let (p, t) = summary_posting("Assets", amount, "");
+                rows.sort_key(&p, &t, &commodities).unwrap().unwrap()
+            })
+            .collect();
+        assert!(keys[1] < keys[2]);
+        assert!(keys[2] < keys[0]);
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        for option in ["--sort", "--display"].iter() {
+            for expression in ["foo(", "amount)>(", ""].iter() {
+                let options = CommonOpts::from_iter(["", "-f", "", option, expression].iter());
+                assert!(Rows::new(&options).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn display_is_not_boolean() {
+        let options = CommonOpts::from_iter(["", "-f", "", "--display", "amount"].iter());
+        let rows = Rows::new(&options).unwrap();
+        let (p, t) = summary_posting("Assets", Money::Zero, "");
+        assert!(rows.is_displayed(&p, &t, &List::new()).is_err());
+    }
+}
@@ -5,15 +5,38 @@ use prettytable::Row;
 use prettytable::Table;
 
 use crate::app::PeriodGroup;
-use crate::commands::balance::convert_balance;
+use crate::commands::balance::{convert_balance, percentage};
 use crate::error::ReportError::CurrencyConversionError;
-use crate::models::{conversion, Balance, Ledger, Money};
+use crate::models::{conversion, Balance, Cost, Currency, HasName, Ledger, Money, Price};
 use crate::parser::value_expr::build_root_node_from_expression;
 use crate::{filter, CommonOpts};
 use chrono::{Datelike, Duration, NaiveDate};
 use num::{BigInt, BigRational, Zero};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// How the results are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// One row per period
+    Periods,
+    /// One row per year, one column per period
+    Calendar,
+    /// One row per account, with the accounts cut at the given depth
+    ByAccount(usize),
+}
+
+/// What the report shows
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub layout: Layout,
+    /// Whether to show the returns over the whole time
+    pub summary: bool,
+    /// A commodity to compare the returns with
+    pub benchmark: Option<String>,
+}
+
 /// ROI (return on investment) report
 pub fn execute(
     options: &CommonOpts,
@@ -21,13 +44,17 @@ pub fn execute(
     cash_flows_query: Vec<String>,
     assets_value_query: Vec<String>,
     frequency: Frequency,
-    calendar: bool,
-    summary: bool,
+    report: &Report,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let ledger = match maybe_ledger {
         Some(ledger) => ledger,
         None => Ledger::try_from(options)?,
     };
+    let layout = report.layout;
+    let benchmark = match &report.benchmark {
+        Some(benchmark) => Some(ledger.commodities.get(benchmark)?.clone()),
+        None => None,
+    };
 
     // TODO exit gracefully
     assert!(
@@ -35,13 +62,13 @@ pub fn execute(
         "cash flows query has to be provided"
     );
     assert!(!assets_value_query.is_empty(), "assets value query");
-    if calendar {
+    if layout == Layout::Calendar {
         assert!(frequency != Frequency::Yearly)
     }
 
     // Prepare the nodes for filtering
     let mut regexes = HashMap::new();
-    let mut query = filter::preprocess_query(&cash_flows_query, &false);
+    let mut query = filter::preprocess_query(&cash_flows_query, &false)?;
     let cash_flows_node = if query.len() > 2 {
         Some(build_root_node_from_expression(
             query.as_str(),
@@ -50,7 +77,7 @@ pub fn execute(
     } else {
         None
     };
-    query = filter::preprocess_query(&assets_value_query, &false);
+    query = filter::preprocess_query(&assets_value_query, &false)?;
     let assets_value_node = if query.len() > 2 {
         Some(build_root_node_from_expression(
             query.as_str(),
@@ -62,178 +89,418 @@ pub fn execute(
 
     // Get a currency
     let mut currency = None;
-    if let Some(c) = &options.exchange {
+    if let Some(c) = options.exchange.first() {
         currency = Some(ledger.commodities.get(c).unwrap().clone());
     }
-    let mut first_date = None;
-    let mut last_date = None;
-
-    let mut periods: Vec<Period> = vec![];
-    let mut cash_flows: Vec<Cashflow> = vec![];
 
+    let mut total = Investment::default();
+    let mut accounts: BTreeMap<String, Investment> = BTreeMap::new();
     for t in ledger.transactions.iter() {
         // cash_flows
+        let mut cash_flows = vec![];
         for p in t.postings.borrow().iter() {
             if !filter::filter(options, &cash_flows_node, t, p, &ledger.commodities)? {
                 continue;
             }
-            let index = get_period_index(p.date, &mut periods, frequency);
-            let period = &mut periods[index];
-
-            if first_date.is_none() {
-                first_date = Some(p.date);
-            }
-            last_date = Some(p.date);
-
             if currency.as_ref().is_none() {
                 currency = Some(p.amount.as_ref().unwrap().get_commodity().unwrap().clone())
             }
-            if p.amount.as_ref().unwrap().get_commodity().unwrap()
-                == currency.as_ref().unwrap().clone()
-            {
-                period.add_cash(p.amount.as_ref().unwrap().to_owned());
-                cash_flows.push(Cashflow {
-                    date: p.date,
-                    value: p.amount.as_ref().unwrap().get_amount().to_f64().unwrap(),
-                });
-            } else {
-                let multipliers =
-                    conversion(currency.as_ref().unwrap().clone(), p.date, &ledger.prices);
-                if let Some(mult) =
-                    multipliers.get(p.amount.as_ref().unwrap().get_commodity().unwrap().as_ref())
-                {
-                    let new_amount = Money::Money {
-                        amount: p.amount.as_ref().unwrap().get_amount() * mult.clone(),
-                        currency: currency.as_ref().unwrap().clone(),
-                    };
-                    period.add_cash(new_amount.clone());
-                    cash_flows.push(Cashflow {
-                        date: p.date,
-                        value: new_amount.get_amount().to_f64().unwrap(),
-                    });
-                } else {
-                    return Err(Box::new(CurrencyConversionError(
-                        p.amount
-                            .as_ref()
-                            .unwrap()
-                            .get_commodity()
-                            .unwrap()
-                            .as_ref()
-                            .clone(),
-                        currency.as_ref().unwrap().as_ref().clone(),
-                    )));
-                }
-            }
+            let amount = p.amount.as_ref().unwrap();
+            let new_amount = convert_money(amount, currency.as_ref().unwrap(), p.date, &ledger)?;
+            cash_flows.push((p.date, new_amount));
         } // cash flows
 
         // balances
+        let mut assets = vec![];
         for p in t.postings.borrow().iter() {
             if !filter::filter(options, &assets_value_node, t, p, &ledger.commodities)? {
                 continue;
             }
-            let index = get_period_index(p.date, &mut periods, frequency);
-            let mut period = &mut periods[index];
-
-            period.final_balance =
-                period.final_balance.clone() + Balance::from(p.amount.as_ref().unwrap().to_owned());
+            assets.push((
+                p.account.get_name().to_string(),
+                p.date,
+                p.amount.clone().unwrap(),
+                p.cost.clone(),
+            ));
         } // balances
-    }
 
-    let mut insertions: Vec<Period> = vec![];
-    periods.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
-
-    let mut last_period_date = None;
-    for p in periods.iter_mut() {
-        if last_period_date.is_none() {
-            last_period_date = Some(p.end);
-            continue;
-        }
-        // Because the gap may be more than one month, we need a loop
-        'inner: loop {
-            let expected = last_period_date.unwrap() + Duration::days(1);
-            last_period_date = Some(period_ending(expected, frequency));
-            if expected == p.start {
-                break 'inner;
+        // The cash flows go to the accounts of the transaction, in proportion to their value
+        if let Layout::ByAccount(depth) = layout {
+            let mut weights: BTreeMap<String, BigRational> = BTreeMap::new();
+            for (account, date, money, cost) in assets.iter() {
+                let account = account
+                    .split(':')
+                    .take(depth)
+                    .collect::<Vec<&str>>()
+                    .join(":");
+                let investment = accounts.entry(account.clone()).or_default();
+                investment.assets.push((*date, money.clone()));
+                // Without cash flows there is nothing to split, nor a currency yet
+                if cash_flows.is_empty() {
+                    continue;
+                }
+                // A commodity bought for the first time has no price yet, but it has a cost
+                let value = match cost {
+                    Some(Cost::PerUnit { amount }) => amount.clone() * money.get_amount(),
+                    Some(Cost::Total { amount }) => amount.clone(),
+                    None => money.clone(),
+                };
+                let value = convert_money(&value, currency.as_ref().unwrap(), *date, &ledger)?;
+                *weights.entry(account).or_default() += value.abs().get_amount();
+            }
+            let total_weight: BigRational = weights.values().sum();
+            let count = BigRational::from_integer(BigInt::from(weights.len()));
+            for (account, weight) in weights.iter() {
+                let share = match total_weight.is_zero() {
+                    false => weight / &total_weight,
+                    true => count.recip(),
+                };
+                let investment = accounts.get_mut(account).unwrap();
+                for (date, money) in cash_flows.iter() {
+                    investment
+                        .cash_flows
+                        .push((*date, money.clone() * share.clone()));
+                }
             }
-            let new_period = Period::from_date(expected, frequency);
-            insertions.push(new_period);
         }
+        total.cash_flows.append(&mut cash_flows);
+        total
+            .assets
+            .extend(assets.into_iter().map(|(_, date, money, _)| (date, money)));
     }
-
-    periods.append(&mut insertions);
-    periods.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
-
-    let mut prev_final_balance = Balance::new();
-    let mut prev_final_money = None;
-    for (i, p) in periods.iter_mut().enumerate() {
-        if i > 0 {
-            p.initial_balance = prev_final_balance;
-            p.initial_money = prev_final_money;
+    let currency = currency.unwrap();
+
+    if let Layout::ByAccount(_) = layout {
+        let mut table = Table::new();
+        table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+        let mut titles = row!["Account", r->"Value (end)", r->"Cash flow", r->"TWR", r->"TWR_y", r->"IRR", r->"Modified Dietz"];
+        if benchmark.is_some() {
+            titles.add_cell(Cell::new("Benchmark").style_spec("r"));
+            titles.add_cell(Cell::new("Excess").style_spec("r"));
+        }
+        table.set_titles(titles);
+        for (account, investment) in accounts.iter() {
+            table.add_row(performance_row(
+                Cell::new(account),
+                investment,
+                frequency,
+                &currency,
+                &ledger.prices,
+                benchmark.as_ref(),
+            )?);
         }
-        p.final_balance = p.final_balance.clone() + p.initial_balance.clone();
+        table.add_row(performance_row(
+            Cell::new("Total").style_spec("b"),
+            &total,
+            frequency,
+            &currency,
+            &ledger.prices,
+            benchmark.as_ref(),
+        )?);
+        table.printstd();
+        println!("Currency used for calculations: {}", currency);
+        return Ok(());
+    }
+
+    let (periods, cash_flows) = total.periods(frequency, &currency, &ledger.prices)?;
+    let benchmark_prices = match &benchmark {
+        Some(benchmark) => Some(benchmark_prices(
+            &periods,
+            benchmark,
+            &currency,
+            &ledger.prices,
+        )),
+        None => None,
+    };
+    match layout {
+        Layout::Calendar => print_calendar(&periods, &frequency, benchmark_prices.as_deref()),
+        _ => print_normal(&periods, options, benchmark_prices.as_deref()),
+    }
 
-        if p.final_money.is_none() {
-            let multipliers = conversion(currency.as_ref().unwrap().clone(), p.end, &ledger.prices);
-            p.final_money = Some(
-                convert_balance(&p.final_balance, &multipliers, currency.as_ref().unwrap())?
-                    .to_money()?,
+    if report.summary {
+        // Add a summary. For example:
+        // Total TWR: 68.38%.
+        // Period: 5.41 years.
+        // Annualized TWR: 10.12%
+        let performance =
+            Performance::new(&total, &periods, &cash_flows, benchmark_prices.as_deref());
+        println!("Currency used for calculations: {}", currency);
+        println!("Total TWR: {:.2}%", performance.twr * 100.0);
+        println!("Period: {:.2} years", performance.years);
+        println!("Annualized TWR: {:.2}%", performance.twr_annualized * 100.0);
+        println!("Annualized IRR: {}", percentage(performance.irr));
+        println!("Modified Dietz: {}", percentage(performance.dietz));
+        if let Some(benchmark) = &benchmark {
+            println!(
+                "Benchmark ({}): {}",
+                benchmark,
+                percentage(performance.benchmark)
             );
-        }
-        if p.initial_money.is_none() {
-            let multipliers =
-                conversion(currency.as_ref().unwrap().clone(), p.start, &ledger.prices);
-            p.initial_money = Some(
-                convert_balance(&p.initial_balance, &multipliers, currency.as_ref().unwrap())?
-                    .to_money()
-                    .unwrap(),
+            println!(
+                "Excess return: {}",
+                percentage(performance.benchmark.map(|x| performance.twr - x))
             );
         }
+    }
+    Ok(())
+}
 
-        prev_final_balance = p.final_balance.clone();
-        prev_final_money = p.final_money.clone();
+/// Converts the amount to the currency with the prices of the date
+fn convert_money(
+    amount: &Money,
+    currency: &Rc<Currency>,
+    date: NaiveDate,
+    ledger: &Ledger,
+) -> Result<Money, Box<dyn std::error::Error>> {
+    let commodity = amount.get_commodity().unwrap();
+    if commodity == *currency {
+        return Ok(amount.clone());
     }
+    let multipliers = conversion(currency.clone(), date, &ledger.prices);
+    match multipliers.get(commodity.as_ref()) {
+        Some(mult) => Ok(Money::Money {
+            amount: amount.get_amount() * mult.clone(),
+            currency: currency.clone(),
+        }),
+        None => Err(Box::new(CurrencyConversionError(
+            commodity.as_ref().clone(),
+            currency.as_ref().clone(),
+        ))),
+    }
+}
 
-    cash_flows.push(Cashflow {
-        date: periods.iter().last().unwrap().end,
-        value: periods
-            .iter()
-            .last()
-            .unwrap()
-            .final_money
-            .as_ref()
-            .unwrap()
-            .get_amount()
-            .to_f64()
-            .unwrap(),
-    });
+/// The price of the benchmark at the start and at the end of a period, if there is one
+type BenchmarkPrices = (Option<f64>, Option<f64>);
+
+/// The prices of the benchmark commodity for every period
+fn benchmark_prices(
+    periods: &[Period],
+    benchmark: &Rc<Currency>,
+    currency: &Rc<Currency>,
+    prices: &[Price],
+) -> Vec<BenchmarkPrices> {
+    let value = |date: NaiveDate| {
+        conversion(currency.clone(), date, prices)
+            .get(benchmark.as_ref())
+            .and_then(|x| x.to_f64())
+    };
+    periods
+        .iter()
+        // Prices of the last day of the period count for its end
+        .map(|p| (value(p.start), value(p.end.succ_opt().unwrap())))
+        .collect()
+}
 
-    match calendar {
-        false => print_normal(&periods, options),
-        true => print_calendar(&periods, &frequency),
+/// The return of holding the benchmark during a period
+fn benchmark_return(prices: &BenchmarkPrices) -> Option<f64> {
+    match prices {
+        (Some(start), Some(end)) if *start != 0.0 => Some(end / start - 1.0),
+        _ => None,
     }
+}
 
-    if summary {
-        // Add a summary. For example:
-        // Total TWR: 68.38%.
-        // Period: 5.41 years.
-        // Annualized TWR: 10.12%
-        let mut total_twr = 1.0;
-        let irr = irr(&cash_flows);
-        for p in periods.iter() {
-            total_twr *= 1.0 + p.twr().to_f64().unwrap();
-            // dbg!(&total_twr);
+/// The return of holding the benchmark during several periods, from the first price at the start
+/// of one of them to the last price at the end of one, so that periods without prices don't
+/// leave it unknown
+fn benchmark_total(prices: &[BenchmarkPrices]) -> Option<f64> {
+    let start = prices.iter().find_map(|x| x.0);
+    let end = prices.iter().rev().find_map(|x| x.1);
+    benchmark_return(&(start, end))
+}
+
+/// Compounds the returns of several periods, only if all of them are known
+fn compound(returns: &[Option<f64>]) -> Option<f64> {
+    let mut total = 1.0;
+    for r in returns.iter() {
+        total *= 1.0 + (*r)?;
+    }
+    Some(total - 1.0)
+}
+
+/// A row of the report by account
+fn performance_row(
+    title: Cell,
+    investment: &Investment,
+    frequency: Frequency,
+    currency: &Rc<Currency>,
+    prices: &[Price],
+    benchmark: Option<&Rc<Currency>>,
+) -> Result<Row, Box<dyn std::error::Error>> {
+    let (periods, cash_flows) = investment.periods(frequency, currency, prices)?;
+    let benchmark_prices =
+        benchmark.map(|benchmark| benchmark_prices(&periods, benchmark, currency, prices));
+    let performance = Performance::new(
+        investment,
+        &periods,
+        &cash_flows,
+        benchmark_prices.as_deref(),
+    );
+    let cash_flow = investment
+        .cash_flows
+        .iter()
+        .fold(Balance::new(), |acc, x| acc + Balance::from(x.1.clone()));
+    let mut cells = vec![
+        title,
+        Cell::new(&format!(
+            "{}",
+            periods.last().unwrap().final_money.as_ref().unwrap()
+        ))
+        .style_spec("r"),
+        Cell::new(&format!("{}", cash_flow.to_money()?)).style_spec("r"),
+        Cell::new(&format!("{:.2}%", performance.twr * 100.0)).style_spec("r"),
+        Cell::new(&format!("{:.2}%", performance.twr_annualized * 100.0)).style_spec("r"),
+        Cell::new(&percentage(performance.irr)).style_spec("r"),
+        Cell::new(&percentage(performance.dietz)).style_spec("r"),
+    ];
+    if benchmark.is_some() {
+        let excess = performance.benchmark.map(|x| performance.twr - x);
+        cells.push(Cell::new(&percentage(performance.benchmark)).style_spec("r"));
+        cells.push(Cell::new(&percentage(excess)).style_spec("r"));
+    }
+    Ok(Row::new(cells))
+}
+
+/// The postings of an investment
+///
+/// The cash flows are already converted to the currency of the report, the assets are converted
+/// with the prices at the end of every period.
+#[derive(Debug, Default)]
+struct Investment {
+    cash_flows: Vec<(NaiveDate, Money)>,
+    assets: Vec<(NaiveDate, Money)>,
+}
+
+impl Investment {
+    /// The periods with their balances, and the cash flows for the IRR
+    fn periods(
+        &self,
+        frequency: Frequency,
+        currency: &Rc<Currency>,
+        prices: &[Price],
+    ) -> Result<(Vec<Period>, Vec<Cashflow>), Box<dyn std::error::Error>> {
+        let mut periods: Vec<Period> = vec![];
+        let mut cash_flows: Vec<Cashflow> = vec![];
+        for (date, money) in self.cash_flows.iter() {
+            let index = get_period_index(*date, &mut periods, frequency);
+            periods[index].add_cash(money.clone());
+            cash_flows.push(Cashflow {
+                date: *date,
+                value: money.get_amount().to_f64().unwrap(),
+            });
+        }
+        for (date, money) in self.assets.iter() {
+            let index = get_period_index(*date, &mut periods, frequency);
+            let period = &mut periods[index];
+            period.final_balance = period.final_balance.clone() + Balance::from(money.clone());
         }
 
-        total_twr -= 1.0;
-        let num_days = ((last_date.unwrap() - first_date.unwrap()).num_days() + 1) as f64;
-        let twr_annualized = (1.0 + total_twr).powf(365.25 / num_days) - 1.0;
-        println!("Currency used for calculations: {}", currency.unwrap());
-        println!("Total TWR: {:.2}%", total_twr * 100.0);
-        println!("Period: {:.2} years", num_days / 365.25);
-        println!("Annualized TWR: {:.2}%", twr_annualized * 100.0);
-        println!("Annualized IRR: {:.2}%", irr * 100.0);
+        let mut insertions: Vec<Period> = vec![];
+        periods.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+
+        let mut last_period_date = None;
+        for p in periods.iter_mut() {
+            if last_period_date.is_none() {
+                last_period_date = Some(p.end);
+                continue;
+            }
+            // Because the gap may be more than one month, we need a loop
+            'inner: loop {
+                let expected = last_period_date.unwrap() + Duration::days(1);
+                last_period_date = Some(period_ending(expected, frequency));
+                if expected == p.start {
+                    break 'inner;
+                }
+                let new_period = Period::from_date(expected, frequency);
+                insertions.push(new_period);
+            }
+        }
+
+        periods.append(&mut insertions);
+        periods.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+
+        let mut prev_final_balance = Balance::new();
+        let mut prev_final_money = None;
+        for (i, p) in periods.iter_mut().enumerate() {
+            if i > 0 {
+                p.initial_balance = prev_final_balance;
+                p.initial_money = prev_final_money;
+            }
+            p.final_balance = p.final_balance.clone() + p.initial_balance.clone();
+
+            if p.final_money.is_none() {
+                let multipliers = conversion(currency.clone(), p.end, prices);
+                p.final_money =
+                    Some(convert_balance(&p.final_balance, &multipliers, currency)?.to_money()?);
+            }
+            if p.initial_money.is_none() {
+                let multipliers = conversion(currency.clone(), p.start, prices);
+                p.initial_money = Some(
+                    convert_balance(&p.initial_balance, &multipliers, currency)?
+                        .to_money()
+                        .unwrap(),
+                );
+            }
+
+            prev_final_balance = p.final_balance.clone();
+            prev_final_money = p.final_money.clone();
+        }
+
+        cash_flows.push(Cashflow {
+            date: periods.iter().last().unwrap().end,
+            value: periods
+                .iter()
+                .last()
+                .unwrap()
+                .final_money
+                .as_ref()
+                .unwrap()
+                .get_amount()
+                .to_f64()
+                .unwrap(),
+        });
+        Ok((periods, cash_flows))
+    }
+}
+
+/// The returns of an investment over its whole life
+struct Performance {
+    twr: f64,
+    twr_annualized: f64,
+    irr: Option<f64>,
+    dietz: Option<f64>,
+    /// The return of the benchmark over the same periods
+    benchmark: Option<f64>,
+    years: f64,
+}
+
+impl Performance {
+    fn new(
+        investment: &Investment,
+        periods: &[Period],
+        cash_flows: &[Cashflow],
+        benchmark: Option<&[BenchmarkPrices]>,
+    ) -> Performance {
+        let mut twr = 1.0;
+        for p in periods.iter() {
+            twr *= 1.0 + p.twr().to_f64().unwrap();
+        }
+        twr -= 1.0;
+        // The returns are measured until the end of the last period
+        let first_date = investment.cash_flows.iter().map(|x| x.0).min();
+        let last_date = periods.last().map(|x| x.end);
+        let num_days = match (first_date, last_date) {
+            (Some(first), Some(last)) => ((last - first).num_days() + 1) as f64,
+            _ => 1.0,
+        };
+        Performance {
+            twr,
+            twr_annualized: (1.0 + twr).powf(365.25 / num_days) - 1.0,
+            irr: irr(cash_flows),
+            dietz: modified_dietz(periods, &investment.cash_flows),
+            benchmark: benchmark.and_then(benchmark_total),
+            years: num_days / 365.25,
+        }
     }
-    Ok(())
 }
 
 /// A cashflow, it does not need full precision and does not need a currency
@@ -243,7 +510,31 @@ struct Cashflow {
     value: f64,
 }
 
-fn irr(cash_flows: &[Cashflow]) -> f64 {
+/// Modified Dietz return over all the periods, the cash flows are weighted by the time they are invested
+fn modified_dietz(periods: &[Period], cash_flows: &[(NaiveDate, Money)]) -> Option<f64> {
+    let first = periods.first()?;
+    let last = periods.last()?;
+    let initial = first.initial_money.as_ref()?.get_amount().to_f64()?;
+    let end = last.final_money.as_ref()?.get_amount().to_f64()?;
+    let total_days = ((last.end - first.start).num_days() + 1) as f64;
+    let mut flows = 0.0;
+    let mut weighted_flows = 0.0;
+    for (date, money) in cash_flows.iter() {
+        // Money that goes out of the cash accounts goes into the investment
+        let flow = -money.get_amount().to_f64()?;
+        let weight = (last.end - *date).num_days() as f64 / total_days;
+        flows += flow;
+        weighted_flows += weight * flow;
+    }
+    let denominator = initial + weighted_flows;
+    match denominator == 0.0 {
+        true => None,
+        false => Some((end - initial - flows) / denominator),
+    }
+}
+
+/// Internal rate of return, with Newton's method or with bisection if it does not converge
+fn irr(cash_flows: &[Cashflow]) -> Option<f64> {
     let tol = 1e-6;
     let mut dif: f64 = &tol * 10.0;
     let mut rate = 0.0;
@@ -256,8 +547,37 @@ fn irr(cash_flows: &[Cashflow]) -> f64 {
         dif = f / df;
         rate -= dif;
     }
-    rate
+    match rate.is_finite() & (rate > -1.0) & (dif.abs() <= tol) {
+        true => Some(rate),
+        false => irr_bisection(cash_flows),
+    }
 }
+
+/// Looks for a change of sign of the net present value between -100% and an ever larger rate
+fn irr_bisection(cash_flows: &[Cashflow]) -> Option<f64> {
+    let mut low: f64 = -0.999_999;
+    let mut high: f64 = 1.0;
+    let f_low = npv(cash_flows, low);
+    while f_low.signum() == npv(cash_flows, high).signum() {
+        high *= 2.0;
+        if !high.is_finite() | (high > 1e6) {
+            return None;
+        }
+    }
+    for _ in 0..200 {
+        let middle = (low + high) / 2.0;
+        let f_middle = npv(cash_flows, middle);
+        if f_middle.abs() < 1e-9 || (high - low) < 1e-12 {
+            return Some(middle);
+        }
+        match f_middle.signum() == f_low.signum() {
+            true => low = middle,
+            false => high = middle,
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
 fn npv(movements: &[Cashflow], rate: f64) -> f64 {
     let mut npv = 0.0;
     let first_date = movements[0].date;
@@ -279,97 +599,124 @@ fn npv_derivative(movements: &[Cashflow], rate: f64) -> f64 {
     }
     npv
 }
-fn print_calendar(periods: &[Period], frequency: &Frequency) {
+/// The column of a period in the calendar, with its TWR and benchmark returns
+type CalendarSlot = (usize, Option<f64>, Option<f64>);
+
+fn print_calendar(
+    periods: &[Period],
+    frequency: &Frequency,
+    benchmark: Option<&[BenchmarkPrices]>,
+) {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
 
     // The title
-    match frequency {
+    let slots = match frequency {
         Frequency::Monthly => {
             table.set_titles(
             row![r->"year", r->"Jan", r->"Feb", r->"Mar", r->"Apr", r->"May", r->"Jun", r->"Jul", r->"Aug", r->"Sep", r->"Oct", r->"Nov", r->"Dec", r->"Total"]
         );
+            12
         }
         Frequency::Quarterly => {
             table.set_titles(
                 row![r->"year", r->"Jan-Mar", r->"Apr-Jun", r->"Jul-Sep", r->"Oct-Dec",r->"Total"],
             );
+            4
         }
         Frequency::Yearly => {
             table.set_titles(row![r->"Total"]);
+            1
         }
-    }
+    };
 
-    // The content
-    let mut content: Vec<Vec<Cell>> = vec![];
-    let mut cum_twr = 1.0;
-    let mut last_year: i32 = periods[0].start.year();
+    let mut years: BTreeMap<i32, Vec<CalendarSlot>> = BTreeMap::new();
     for (i, p) in periods.iter().enumerate() {
-        let year = p.start.year();
-        let cell_content = format!("{:.2}%", p.twr().to_f64().unwrap() * 100.0);
-        let new_row = (i == 0) | (year != last_year);
-        let mut num_rows = content.len();
-
-        if new_row {
-            content.push(vec![cell!(format!("{}", year))]);
-            if i > 0 {
-                content[num_rows - 1].push(cell!(r->format!("{:.2}%", (cum_twr - 1.0) * 100.0)));
-            }
-            cum_twr = 1.0;
-            num_rows += 1;
-        }
+        let slot = match frequency {
+            Frequency::Monthly => p.start.month0() as usize,
+            Frequency::Quarterly => (p.start.month0() / 3) as usize,
+            Frequency::Yearly => 0,
+        };
+        years.entry(p.start.year()).or_default().push((
+            slot,
+            p.twr().to_f64(),
+            benchmark.and_then(|b| benchmark_return(&b[i])),
+        ));
+    }
 
-        if i == 0 {
-            // Push empty cells until we go to the right place
-            let num_period = match frequency {
-                Frequency::Monthly => p.start.month() as usize,
-                Frequency::Quarterly => ((p.start.month() - 1) / 3 + 1) as usize,
-                Frequency::Yearly => 1,
-            };
-            for _ in 1..num_period {
-                content[0].push(cell!(""));
-            }
+    // The content
+    let calendar_row = |title: String, returns: &[(usize, Option<f64>)], total: Option<f64>| {
+        let mut row = Row::new(vec![Cell::new(&title)]);
+        for slot in 0..slots {
+            row.add_cell(match returns.iter().find(|x| x.0 == slot) {
+                Some((_, value)) => Cell::new(&percentage(*value)).style_spec("r"),
+                None => Cell::new(""),
+            });
         }
-
-        cum_twr *= 1.0 + p.twr().to_f64().unwrap();
-        content[num_rows - 1].push(cell!(r-> cell_content));
-        last_year = year;
-    }
-    match content.len() {
-        1 => content[0].push(cell!(format!("{:.2}%", (cum_twr - 1.0) * 100.0))),
-        x => {
-            let j = content[0].len() - 1;
-            let i = content[x - 1].len();
-            for _ in i..j {
-                content[x - 1].push(cell!(""));
-            }
-            content[x - 1].push(cell!(r->format!("{:.2}%", (cum_twr - 1.0) * 100.0)));
+        row.add_cell(Cell::new(&percentage(total)).style_spec("r"));
+        row
+    };
+    for (year, returns) in years.iter() {
+        let twr: Vec<(usize, Option<f64>)> = returns.iter().map(|x| (x.0, x.1)).collect();
+        let twr_total = compound(&twr.iter().map(|x| x.1).collect::<Vec<_>>());
+        table.add_row(calendar_row(format!("{}", year), &twr, twr_total));
+        if let Some(prices) = benchmark {
+            let year_prices: Vec<BenchmarkPrices> = periods
+                .iter()
+                .zip(prices.iter())
+                .filter(|(p, _)| p.start.year() == *year)
+                .map(|(_, x)| *x)
+                .collect();
+            let benchmark: Vec<(usize, Option<f64>)> = returns.iter().map(|x| (x.0, x.2)).collect();
+            let benchmark_total = benchmark_total(&year_prices);
+            let excess: Vec<(usize, Option<f64>)> = returns
+                .iter()
+                .map(|x| (x.0, x.1.zip(x.2).map(|(a, b)| a - b)))
+                .collect();
+            // The excess of the year is not compounded, it is the difference of the totals
+            let excess_total = twr_total.zip(benchmark_total).map(|(a, b)| a - b);
+            table.add_row(calendar_row(
+                "  benchmark".to_string(),
+                &benchmark,
+                benchmark_total,
+            ));
+            table.add_row(calendar_row("  excess".to_string(), &excess, excess_total));
         }
     }
-    for row in content.iter() {
-        table.add_row(Row::new(row.to_vec()));
-    }
 
     // Print the table to stdout
     table.printstd();
 }
 
-fn print_normal(periods: &[Period], options: &CommonOpts) {
+fn print_normal(periods: &[Period], options: &CommonOpts, benchmark: Option<&[BenchmarkPrices]>) {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
-    table.set_titles(
-        row![r->"Begin", r->"End", r->"Value (begin)", r->"Cash flow", r->"Value (end)", r->"TWR", r->"TWR_y"],
-    );
-    for p in periods.iter() {
-        table.add_row(row![
+    let mut titles =
+        row![r->"Begin", r->"End", r->"Value (begin)", r->"Cash flow", r->"Value (end)", r->"TWR"];
+    if benchmark.is_some() {
+        titles.add_cell(Cell::new("Benchmark").style_spec("r"));
+        titles.add_cell(Cell::new("Excess").style_spec("r"));
+    }
+    titles.add_cell(Cell::new("TWR_y").style_spec("r"));
+    table.set_titles(titles);
+    for (i, p) in periods.iter().enumerate() {
+        let twr = (&p.twr() * BigInt::from(100)).to_f64().unwrap();
+        let mut row = row![
             format!("{}", p.start.format(&options.date_format)),
             format!("{}", p.end.format(&options.date_format)),
             r->format!("{}", p.initial_money.as_ref().unwrap()),
             r->format!("{}", p.cash_flow),
             r->format!("{}", p.final_money.as_ref().unwrap()),
-            r->format!("{:.2}%", (&p.twr() * BigInt::from(100)).to_f64().unwrap()),
-            r->format!("{:.2}%", p.twr_annualized() * 100_f64),
-        ]);
+            r->format!("{:.2}%", twr),
+        ];
+        if let Some(benchmark) = benchmark {
+            let benchmark = benchmark_return(&benchmark[i]);
+            let excess = benchmark.map(|x| twr / 100.0 - x);
+            row.add_cell(Cell::new(&percentage(benchmark)).style_spec("r"));
+            row.add_cell(Cell::new(&percentage(excess)).style_spec("r"));
+        }
+        row.add_cell(Cell::new(&format!("{:.2}%", p.twr_annualized() * 100_f64)).style_spec("r"));
+        table.add_row(row);
     }
     // Print the table to stdout
     table.printstd();
@@ -460,7 +807,7 @@ impl From<PeriodGroup> for Frequency {
     }
 }
 /// Returns the first day of the month
-fn period_beginning(d: NaiveDate, frequency: Frequency) -> NaiveDate {
+pub(crate) fn period_beginning(d: NaiveDate, frequency: Frequency) -> NaiveDate {
     match frequency {
         Frequency::Monthly => NaiveDate::from_ymd(d.year(), d.month(), 1),
         Frequency::Quarterly => NaiveDate::from_ymd(d.year(), ((d.month() - 1) / 3) * 3 + 1, 1),
@@ -469,7 +816,7 @@ fn period_beginning(d: NaiveDate, frequency: Frequency) -> NaiveDate {
 }
 
 /// Returns the last day of the period
-fn period_ending(d: NaiveDate, frequency: Frequency) -> NaiveDate {
+pub(crate) fn period_ending(d: NaiveDate, frequency: Frequency) -> NaiveDate {
     // Find the beginning of the next period and subtract one day
     let month: u32;
 
@@ -553,4 +900,35 @@ mod test {
             NaiveDate::from_ymd(2019, 1, 1)
         );
     }
+
+    #[test]
+    fn irr_falls_back_to_bisection() {
+        let date = |m, d| NaiveDate::from_ymd_opt(2021, m, d).unwrap();
+        // Losing almost everything, Newton's method jumps below -100% and returns NaN
+        let cash_flows = vec![
+            Cashflow {
+                date: date(1, 1),
+                value: -100.0,
+            },
+            Cashflow {
+                date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+                value: 1.0,
+            },
+        ];
+        let rate = irr(&cash_flows).unwrap();
+        assert!((rate + 0.99).abs() < 1e-3);
+
+        // There is no rate that makes the net present value zero
+        let cash_flows = vec![
+            Cashflow {
+                date: date(1, 1),
+                value: 100.0,
+            },
+            Cashflow {
+                date: date(2, 1),
+                value: 200.0,
+            },
+        ];
+        assert_eq!(irr(&cash_flows), None);
+    }
 }
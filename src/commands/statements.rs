@@ -0,0 +1,339 @@
+//! Financial statements: balance sheet, income statement and cash flow
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+
+use prettytable::format;
+use prettytable::{Cell, Row, Table};
+use regex::Regex;
+
+use crate::app::StatementSections;
+use crate::commands::balance::convert_balance;
+use crate::commands::roi::{period_beginning, period_ending, Frequency};
+use crate::models::{conversion, Account, AccountType, Balance, HasName, Ledger, Money};
+use crate::parser::value_expr::build_root_node_from_expression;
+use crate::{filter, CommonOpts};
+use chrono::NaiveDate;
+
+/// The available financial statements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Statement {
+    /// Assets and liabilities at the end of each period
+    BalanceSheet,
+    /// Revenues and expenses during each period
+    IncomeStatement,
+    /// Movements of the cash accounts during each period
+    CashFlow,
+}
+
+/// A group of accounts in a statement
+struct Section {
+    title: &'static str,
+    account_type: AccountType,
+    regex: Option<Regex>,
+    /// Accounts that are normally negative (liabilities, revenues) are shown with the opposite sign
+    negate: bool,
+}
+
+impl Section {
+    fn new(
+        title: &'static str,
+        account_type: AccountType,
+        regex: &Option<String>,
+        negate: bool,
+    ) -> Result<Section, Box<dyn std::error::Error>> {
+        let regex = match regex {
+            Some(r) => Some(Regex::new(format!("(?i){}", r).as_str())?),
+            None => None,
+        };
+        Ok(Section {
+            title,
+            account_type,
+            regex,
+            negate,
+        })
+    }
+
+    /// Whether the account belongs to the section, by regex if provided or by account type otherwise
+    fn contains(&self, account: &Account) -> bool {
+        match &self.regex {
+            Some(regex) => regex.is_match(account.get_name()),
+            None => matches!(account.get_account_type(), Some(x) if x.is(self.account_type)),
+        }
+    }
+}
+
+impl Statement {
+    fn title(&self) -> &'static str {
+        match self {
+            Statement::BalanceSheet => "Balance Sheet",
+            Statement::IncomeStatement => "Income Statement",
+            Statement::CashFlow => "Cash Flow",
+        }
+    }
+    fn sections(
+        &self,
+        regexes: &StatementSections,
+    ) -> Result<Vec<Section>, Box<dyn std::error::Error>> {
+        Ok(match self {
+            Statement::BalanceSheet => vec![
+                Section::new("Assets", AccountType::Asset, &regexes.assets, false)?,
+                Section::new(
+                    "Liabilities",
+                    AccountType::Liability,
+                    &regexes.liabilities,
+                    true,
+                )?,
+            ],
+            Statement::IncomeStatement => vec![
+                Section::new("Revenues", AccountType::Revenue, &regexes.revenues, true)?,
+                Section::new("Expenses", AccountType::Expense, &regexes.expenses, false)?,
+            ],
+            Statement::CashFlow => vec![Section::new(
+                "Cash flows",
+                AccountType::Cash,
+                &regexes.cash,
+                false,
+            )?],
+        })
+    }
+    /// The balance sheet shows balances, the rest show the changes within the period
+    fn is_cumulative(&self) -> bool {
+        matches!(self, Statement::BalanceSheet)
+    }
+}
+
+/// Financial statement report
+pub fn execute(
+    options: &CommonOpts,
+    maybe_ledger: Option<Ledger>,
+    statement: Statement,
+    frequency: Option<Frequency>,
+    regexes: &StatementSections,
+    show_total: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = match maybe_ledger {
+        Some(ledger) => ledger,
+        None => Ledger::try_from(options)?,
+    };
+    let sections = statement.sections(regexes)?;
+
+    let mut regexes = HashMap::new();
+    let query = filter::preprocess_query(&options.query, &options.related)?;
+    let node = if query.len() > 2 {
+        Some(build_root_node_from_expression(
+            query.as_str(),
+            &mut regexes,
+        ))
+    } else {
+        None
+    };
+
+    // Dates are handled here because the balance sheet needs everything before the beginning
+    let mut unbounded = options.clone();
+    unbounded.begin = None;
+    unbounded.end = None;
+    unbounded.period = None;
+    let begin = [options.begin, options.period.map(|x| x.0)]
+        .iter()
+        .flatten()
+        .max()
+        .copied();
+    let end = [options.end, options.period.map(|x| x.1)]
+        .iter()
+        .flatten()
+        .min()
+        .copied();
+
+    // (section, account, date, amount)
+    let mut postings: Vec<(usize, String, NaiveDate, Money)> = vec![];
+    for t in ledger.transactions.iter() {
+        for p in t.postings.borrow().iter() {
+            if matches!(end, Some(end) if p.date >= end) {
+                continue;
+            }
+            if !statement.is_cumulative() & matches!(begin, Some(begin) if p.date < begin) {
+                continue;
+            }
+            if !filter::filter(&unbounded, &node, t, p, &ledger.commodities)? {
+                continue;
+            }
+            if let Some(index) = sections.iter().position(|s| s.contains(&p.account)) {
+                let mut account = p.account.get_name().to_string();
+                if let Some(depth) = options.depth {
+                    account = account
+                        .split(':')
+                        .take(depth)
+                        .collect::<Vec<&str>>()
+                        .join(":");
+                }
+                let money = match options.exchange.is_empty() {
+                    true => p.amount.clone().unwrap(),
+                    false => p.exchange_amount(),
+                };
+                postings.push((index, account, p.date, money));
+            }
+        }
+    }
+    if postings.is_empty() {
+        return Ok(());
+    }
+
+    // The columns of the report
+    let first = match begin {
+        Some(begin) => begin,
+        None => postings.iter().map(|x| x.2).min().unwrap(),
+    };
+    let last = match end {
+        Some(end) => end.pred_opt().unwrap(),
+        None => postings.iter().map(|x| x.2).max().unwrap(),
+    };
+    let columns = report_columns(first, last, frequency);
+
+    // Balances by section and account, one per column
+    let mut balances: Vec<BTreeMap<String, Vec<Balance>>> =
+        sections.iter().map(|_| BTreeMap::new()).collect();
+    for (section, account, date, money) in postings.iter() {
+        let account_balances = balances[*section]
+            .entry(account.clone())
+            .or_insert_with(|| vec![Balance::new(); columns.len()]);
+        for (i, (start, end)) in columns.iter().enumerate() {
+            let included = match statement.is_cumulative() {
+                true => date <= end,
+                false => (date >= start) & (date <= end),
+            };
+            if included {
+                account_balances[i] = account_balances[i].clone() + Balance::from(money.clone());
+            }
+        }
+    }
+
+    // Conversion to a single currency, with the prices at the end of each period
+    if let Some(currency_string) = options.exchange.first() {
+        let currency = ledger.commodities.get(currency_string)?;
+        for (i, (_, end)) in columns.iter().enumerate() {
+            let multipliers = conversion(currency.clone(), end.succ_opt().unwrap(), &ledger.prices);
+            for section_balances in balances.iter_mut() {
+                for account_balances in section_balances.values_mut() {
+                    account_balances[i] =
+                        convert_balance(&account_balances[i], &multipliers, currency)?;
+                }
+            }
+        }
+    }
+
+    // Print the statement
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    let mut titles = vec![Cell::new(statement.title())];
+    for (start, end) in columns.iter() {
+        let title = match statement.is_cumulative() {
+            true => format!("{}", end.format(&options.date_format)),
+            false => format!(
+                "{} - {}",
+                start.format(&options.date_format),
+                end.format(&options.date_format)
+            ),
+        };
+        titles.push(Cell::new(&title).style_spec("r"));
+    }
+    table.set_titles(Row::new(titles));
+
+    let mut net = vec![Balance::new(); columns.len()];
+    for (section, section_balances) in sections.iter().zip(balances.iter()) {
+        table.add_row(Row::new(vec![Cell::new(section.title).style_spec("b")]));
+        let mut totals = vec![Balance::new(); columns.len()];
+        for (account, account_balances) in section_balances.iter() {
+            if account_balances.iter().all(|x| x.is_zero()) {
+                continue;
+            }
+            let mut row = vec![Cell::new(&format!("  {}", account))];
+            for (i, balance) in account_balances.iter().enumerate() {
+                totals[i] = totals[i].clone() + balance.clone();
+                row.push(balance_cell(balance, section.negate));
+            }
+            table.add_row(Row::new(row));
+        }
+        if show_total {
+            let mut row = vec![Cell::new("  Total")];
+            for (i, total) in totals.iter().enumerate() {
+                row.push(balance_cell(total, section.negate).style_spec("rb"));
+                net[i] = net[i].clone() + total.clone();
+            }
+            table.add_row(Row::new(row));
+        }
+    }
+    if show_total & (sections.len() > 1) {
+        let mut row = vec![Cell::new("Net").style_spec("b")];
+        // The net result of the income statement is positive when there are profits
+        let negate = statement == Statement::IncomeStatement;
+        for balance in net.iter() {
+            row.push(balance_cell(balance, negate).style_spec("rb"));
+        }
+        table.add_row(Row::new(row));
+    }
+    table.printstd();
+    Ok(())
+}
+
+/// Contiguous periods from the first date to the last one, or a single one if there is no grouping
+pub(crate) fn report_columns(
+    first: NaiveDate,
+    last: NaiveDate,
+    frequency: Option<Frequency>,
+) -> Vec<(NaiveDate, NaiveDate)> {
+    match frequency {
+        None => vec![(first, last)],
+        Some(frequency) => {
+            let mut columns = vec![];
+            let mut start = period_beginning(first, frequency);
+            while start <= last {
+                let end = period_ending(start, frequency);
+                columns.push((start, end));
+                start = end.succ_opt().unwrap();
+            }
+            columns
+        }
+    }
+}
+
+/// A cell with every currency of the balance in its own line
+pub(crate) fn balance_cell(balance: &Balance, negate: bool) -> Cell {
+    let balance = match negate {
+        true => -balance.clone(),
+        false => balance.clone(),
+    };
+    let mut amounts: Vec<&Money> = balance.balance.values().filter(|x| !x.is_zero()).collect();
+    amounts.sort_by_key(|x| x.get_commodity().map(|c| c.get_name().to_string()));
+    let text = match amounts.is_empty() {
+        true => "0".to_string(),
+        false => amounts
+            .iter()
+            .map(|x| format!("{}", x))
+            .collect::<Vec<String>>()
+            .join("\n"),
+    };
+    Cell::new(&text).style_spec("r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monthly_columns() {
+        let date = |m, d| NaiveDate::from_ymd_opt(2021, m, d).unwrap();
+        let columns = report_columns(date(1, 15), date(3, 2), Some(Frequency::Monthly));
+        assert_eq!(
+            columns,
+            vec![
+                (date(1, 1), date(1, 31)),
+                (date(2, 1), date(2, 28)),
+                (date(3, 1), date(3, 31))
+            ]
+        );
+        assert_eq!(
+            report_columns(date(1, 15), date(3, 2), None),
+            vec![(date(1, 15), date(3, 2))]
+        );
+    }
+}
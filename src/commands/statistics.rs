@@ -1,11 +1,25 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::TryFrom;
 
-use crate::models::Ledger;
-use crate::CommonOpts;
+use chrono::NaiveDate;
+use num::{BigInt, BigRational, Signed, Zero};
+
+use crate::commands::tags::Usage;
+use crate::models::{Cleared, Cost, Currency, FromDirective, HasName, Ledger, Money};
+use crate::parser::value_expr::build_root_node_from_expression;
+use crate::{filter, CommonOpts};
+use std::rc::Rc;
+
+/// How many of the largest postings and of the most frequent payees are shown
+const TOP: usize = 5;
 
 /// Statistics command
 ///
-/// Prints summary statistics from the ledger
+/// Prints summary statistics of the postings that pass the filters: counts, dates, uncleared
+/// postings and the average size of the transactions, and then the usage of every account,
+/// payee and commodity. Declared accounts and commodities that are never used are listed at the
+/// end, whatever the filters.
 pub fn execute(
     options: &CommonOpts,
     maybe_ledger: Option<Ledger>,
@@ -15,10 +29,90 @@ pub fn execute(
         None => Ledger::try_from(options)?,
     };
 
-    // Number of transactions
+    let mut regexes = HashMap::new();
+    let query = filter::preprocess_query(&options.query, &options.related)?;
+    let node = if query.len() > 2 {
+        Some(build_root_node_from_expression(
+            query.as_str(),
+            &mut regexes,
+        ))
+    } else {
+        None
+    };
+
     let mut num_postings = 0;
+    let mut num_transactions = 0;
+    let mut num_uncleared = 0;
+    let mut num_pending = 0;
+    let mut dates: Option<(NaiveDate, NaiveDate)> = None;
+    // (usage, uncleared postings) by account
+    let mut accounts: BTreeMap<String, (Usage, usize)> = BTreeMap::new();
+    let mut payees: BTreeMap<String, Usage> = BTreeMap::new();
+    let mut commodities: BTreeMap<String, Usage> = BTreeMap::new();
+    // (date, account, amount) of the postings with an amount
+    let mut amounts: Vec<(NaiveDate, String, Money)> = vec![];
+    // (currency, total of the positive amounts, number of transactions) by commodity
+    let mut sizes: BTreeMap<String, (Rc<Currency>, BigRational, usize)> = BTreeMap::new();
     for t in ledger.transactions.iter() {
-        num_postings += t.postings.borrow().iter().count();
+        let mut included = false;
+        let mut size: HashMap<Rc<Currency>, BigRational> = HashMap::new();
+        for p in t.postings.borrow().iter() {
+            if !filter::filter(options, &node, t, p, &ledger.commodities)? {
+                continue;
+            }
+            included = true;
+            num_postings += 1;
+            let (usage, uncleared) = accounts
+                .entry(p.account.get_name().to_string())
+                .or_insert_with(|| (Usage::new(p.date), 0));
+            usage.add(p.date);
+            match p.cleared {
+                Cleared::Unknown | Cleared::NotCleared => {
+                    num_uncleared += 1;
+                    *uncleared += 1;
+                }
+                Cleared::Pending => num_pending += 1,
+                Cleared::Cleared => (),
+            }
+            if let Some(Money::Money { currency, amount }) = &p.amount {
+                commodities
+                    .entry(currency.get_name().to_string())
+                    .or_insert_with(|| Usage::new(p.date))
+                    .add(p.date);
+                if amount.is_positive() {
+                    *size.entry(currency.clone()).or_default() += amount;
+                }
+                amounts.push((
+                    p.date,
+                    p.account.get_name().to_string(),
+                    p.amount.clone().unwrap(),
+                ));
+            }
+        }
+        if !included {
+            continue;
+        }
+        num_transactions += 1;
+        let date = t.date.unwrap();
+        dates = match dates {
+            Some((first, last)) => Some((first.min(date), last.max(date))),
+            None => Some((date, date)),
+        };
+        let payee = match t.get_payee(&ledger.payees) {
+            Some(payee) => payee.get_name().to_string(),
+            None => t.description.clone(),
+        };
+        payees
+            .entry(payee)
+            .or_insert_with(|| Usage::new(date))
+            .add(date);
+        for (currency, amount) in size.into_iter() {
+            let (_, total, count) = sizes
+                .entry(currency.get_name().to_string())
+                .or_insert_with(|| (currency.clone(), BigRational::zero(), 0));
+            *total += amount;
+            *count += 1;
+        }
     }
 
     let num_files = ledger.files.len();
@@ -31,44 +125,162 @@ pub fn execute(
         }
     }
 
-    let first_transaction_date = &ledger.transactions.get(0).unwrap().date.unwrap();
-    let last_transaction_date = &ledger
-        .transactions
-        .iter()
-        .rev()
-        .next()
-        .unwrap()
-        .date
-        .unwrap();
-    let num_days = 1 + last_transaction_date
-        .signed_duration_since(*first_transaction_date)
-        .num_days();
     // Print the stats
     println!("{} postings", num_postings);
-    println!("{} transactions", &ledger.transactions.len());
-
-    println!(
-        "First transaction: {}",
-        first_transaction_date.format(&options.date_format)
-    );
-    println!(
-        "Last transaction: {}",
-        last_transaction_date.format(&options.date_format)
-    );
-    println!("{} days between first and last transaction", num_days);
-    println!(
-        "{:.2} transactions per day (average)",
-        (ledger.transactions.len() as f64) / (num_days as f64)
-    );
-    println!(
-        "{:.2} postings per day (average)",
-        (num_postings as f64) / (num_days as f64)
-    );
+    println!("{} transactions", num_transactions);
+    if let Some((first_transaction_date, last_transaction_date)) = dates {
+        let num_days = 1 + last_transaction_date
+            .signed_duration_since(first_transaction_date)
+            .num_days();
+        println!(
+            "First transaction: {}",
+            first_transaction_date.format(&options.date_format)
+        );
+        println!(
+            "Last transaction: {}",
+            last_transaction_date.format(&options.date_format)
+        );
+        println!("{} days between first and last transaction", num_days);
+        println!(
+            "{:.2} transactions per day (average)",
+            (num_transactions as f64) / (num_days as f64)
+        );
+        println!(
+            "{:.2} postings per day (average)",
+            (num_postings as f64) / (num_days as f64)
+        );
+    }
+    println!("{} uncleared postings", num_uncleared);
+    println!("{} pending postings", num_pending);
 
     println!("{} price entries", &ledger.prices.len());
     println!("{} different accounts", &ledger.accounts.len());
     println!("{} different payees", &ledger.payees.len());
     println!("{} different commodities", &ledger.commodities.len());
 
+    // The sum of the positive amounts of a transaction, by commodity
+    if !sizes.is_empty() {
+        println!();
+        println!("Average transaction size:");
+        for (currency, total, count) in sizes.values() {
+            let average = total / BigRational::from_integer(BigInt::from(*count));
+            println!(
+                "{:>20}",
+                format!("{}", Money::from((currency.clone(), average)))
+            );
+        }
+    }
+
+    if !accounts.is_empty() {
+        println!();
+        println!(
+            "{:<40}{:>8}{:>10}  First and last posting",
+            "Account", "Postings", "Uncleared"
+        );
+        for (account, (usage, uncleared)) in accounts.iter() {
+            println!(
+                "{:<40}{:>8}{:>10}  {}  {}",
+                account,
+                usage.count,
+                uncleared,
+                usage.first.format(&options.date_format),
+                usage.last.format(&options.date_format)
+            );
+        }
+    }
+
+    if !payees.is_empty() {
+        println!();
+        println!("Most frequent payees:");
+        let mut frequent: Vec<(&String, &Usage)> = payees.iter().collect();
+        frequent.sort_by_key(|x| Reverse(x.1.count));
+        for (payee, usage) in frequent.into_iter().take(TOP) {
+            usage.print(payee, options);
+        }
+    }
+
+    if !commodities.is_empty() {
+        println!();
+        println!("Commodities:");
+        for (commodity, usage) in commodities.iter() {
+            usage.print(commodity, options);
+        }
+    }
+
+    // The largest amounts of every commodity
+    if !amounts.is_empty() {
+        println!();
+        println!("Largest postings:");
+        let mut by_commodity: BTreeMap<String, Vec<&(NaiveDate, String, Money)>> = BTreeMap::new();
+        for posting in amounts.iter() {
+            let commodity = posting.2.get_commodity().unwrap();
+            by_commodity
+                .entry(commodity.get_name().to_string())
+                .or_default()
+                .push(posting);
+        }
+        for postings in by_commodity.values_mut() {
+            postings.sort_by_key(|x| Reverse(x.2.abs()));
+            for (date, account, amount) in postings.iter().take(TOP) {
+                println!(
+                    "{}  {:<40}{:>20}",
+                    date.format(&options.date_format),
+                    account,
+                    format!("{}", amount)
+                );
+            }
+        }
+    }
+
+    // Whatever the filters, declarations that nothing in the journal uses
+    let mut used_accounts = HashSet::new();
+    let mut used_commodities = HashSet::new();
+    for t in ledger.transactions.iter() {
+        for p in t.postings.borrow().iter() {
+            used_accounts.insert(p.account.get_name().to_string());
+            let cost = match &p.cost {
+                Some(Cost::PerUnit { amount }) | Some(Cost::Total { amount }) => Some(amount),
+                None => None,
+            };
+            for money in p.amount.iter().chain(cost) {
+                if let Some(currency) = money.get_commodity() {
+                    used_commodities.insert(currency.get_name().to_string());
+                }
+            }
+        }
+    }
+    for price in ledger.prices.iter() {
+        used_commodities.insert(price.get_commodity().get_name().to_string());
+        if let Some(currency) = price.get_price().get_commodity() {
+            used_commodities.insert(currency.get_name().to_string());
+        }
+    }
+    let unused_accounts: BTreeSet<&str> = ledger
+        .accounts
+        .values()
+        .filter(|x| x.is_from_directive() & !used_accounts.contains(x.get_name()))
+        .map(|x| x.get_name())
+        .collect();
+    let unused_commodities: BTreeSet<&str> = ledger
+        .commodities
+        .values()
+        .filter(|x| x.is_from_directive() & !used_commodities.contains(x.get_name()))
+        .map(|x| x.get_name())
+        .collect();
+    if !unused_accounts.is_empty() {
+        println!();
+        println!("Unused accounts:");
+        for account in unused_accounts.iter() {
+            println!("\t{}", account);
+        }
+    }
+    if !unused_commodities.is_empty() {
+        println!();
+        println!("Unused commodities:");
+        for commodity in unused_commodities.iter() {
+            println!("\t{}", commodity);
+        }
+    }
+
     Ok(())
 }
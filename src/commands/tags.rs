@@ -0,0 +1,104 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::error::Error;
+
+use chrono::NaiveDate;
+
+use crate::models::{HasName, Ledger};
+use crate::parser::value_expr::build_root_node_from_expression;
+use crate::{filter, CommonOpts};
+
+/// How many times something is used and when it was used for the first and the last time
+#[derive(Debug, Clone)]
+pub(crate) struct Usage {
+    pub(crate) count: usize,
+    pub(crate) first: NaiveDate,
+    pub(crate) last: NaiveDate,
+}
+
+impl Usage {
+    pub(crate) fn new(date: NaiveDate) -> Usage {
+        Usage {
+            count: 0,
+            first: date,
+            last: date,
+        }
+    }
+    pub(crate) fn add(&mut self, date: NaiveDate) {
+        self.count += 1;
+        self.first = self.first.min(date);
+        self.last = self.last.max(date);
+    }
+    /// Prints a line of the report
+    pub(crate) fn print(&self, name: &str, options: &CommonOpts) {
+        println!(
+            "{:<40}{:>8}  {}  {}",
+            name,
+            self.count,
+            self.first.format(&options.date_format),
+            self.last.format(&options.date_format)
+        );
+    }
+}
+
+/// Lists the tags of the postings, with the number of postings and the dates they were used
+pub fn execute(
+    options: &CommonOpts,
+    maybe_ledger: Option<Ledger>,
+    values: bool,
+) -> Result<(), Box<dyn Error>> {
+    let ledger = match maybe_ledger {
+        Some(ledger) => ledger,
+        None => Ledger::try_from(options)?,
+    };
+
+    let mut regexes = HashMap::new();
+    let query = filter::preprocess_query(&options.query, &options.related)?;
+    let node = if query.len() > 2 {
+        Some(build_root_node_from_expression(
+            query.as_str(),
+            &mut regexes,
+        ))
+    } else {
+        None
+    };
+
+    let mut tags: BTreeMap<String, (Usage, BTreeMap<String, Usage>)> = BTreeMap::new();
+    for t in ledger.transactions.iter() {
+        for p in t.postings.borrow().iter() {
+            if !filter::filter(options, &node, t, p, &ledger.commodities)? {
+                continue;
+            }
+            // A tag counts once per posting, even if it is both in the transaction and the posting
+            let mut seen = HashSet::new();
+            for tag in p.tags.borrow().iter() {
+                if !seen.insert(tag.get_name().to_string()) {
+                    continue;
+                }
+                let (usage, tag_values) = tags
+                    .entry(tag.get_name().to_string())
+                    .or_insert_with(|| (Usage::new(p.date), BTreeMap::new()));
+                usage.add(p.date);
+                if let Some(value) = tag.value.as_ref().filter(|x| !x.is_empty()) {
+                    tag_values
+                        .entry(value.clone())
+                        .or_insert_with(|| Usage::new(p.date))
+                        .add(p.date);
+                }
+            }
+        }
+    }
+
+    for (name, (usage, tag_values)) in tags.iter() {
+        if options.strict & !ledger.tags.iter().any(|x| x.get_name() == name) {
+            eprintln!("Warning: tag {} not declared.", name);
+        }
+        usage.print(name, options);
+        if values {
+            for (value, value_usage) in tag_values.iter() {
+                value_usage.print(&format!("  {}", value), options);
+            }
+        }
+    }
+    Ok(())
+}
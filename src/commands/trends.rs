@@ -0,0 +1,341 @@
+//! Spending trends
+//!
+//! The postings of the query are added up by account and month. Every account shows its
+//! months, the change of the last month from the month before and from the same month a year
+//! earlier, and the months that are unusually far from the mean of the year before them.
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+
+use chrono::{Months, NaiveDate};
+use num::{BigInt, BigRational, Signed, ToPrimitive, Zero};
+use prettytable::format;
+use prettytable::{Cell, Row, Table};
+
+use crate::commands::balance::{convert_balance, percentage};
+use crate::commands::roi::Frequency;
+use crate::commands::statements::report_columns;
+use crate::error::MixedCommoditiesError;
+use crate::models::{conversion, Balance, Currency, HasName, Ledger, Money};
+use crate::parser::value_expr::build_root_node_from_expression;
+use crate::{filter, CommonOpts};
+use std::rc::Rc;
+
+/// Months before the report, for the year over year change and the unusual months
+const HISTORY: usize = 12;
+/// Months of history an account needs for its months to be unusual
+const MIN_HISTORY: usize = 3;
+/// Shades of the heatmap, from nothing to the largest month of the account
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// The monthly amounts of an account, in a single commodity
+struct Trend {
+    currency: Option<Rc<Currency>>,
+    values: Vec<BigRational>,
+}
+
+pub fn execute(
+    options: &CommonOpts,
+    maybe_ledger: Option<Ledger>,
+    heatmap: bool,
+    deviations: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ledger = match maybe_ledger {
+        Some(ledger) => ledger,
+        None => Ledger::try_from(options)?,
+    };
+
+    let mut regexes = HashMap::new();
+    let query = filter::preprocess_query(&options.query, &options.related)?;
+    let node = if query.len() > 2 {
+        Some(build_root_node_from_expression(
+            query.as_str(),
+            &mut regexes,
+        ))
+    } else {
+        None
+    };
+
+    // The year over year change of the first months needs the year before them
+    let mut unbounded = options.clone();
+    unbounded.begin = None;
+    unbounded.end = None;
+    unbounded.period = None;
+    let begin = [options.begin, options.period.map(|x| x.0)]
+        .iter()
+        .flatten()
+        .max()
+        .copied();
+    let end = [options.end, options.period.map(|x| x.1)]
+        .iter()
+        .flatten()
+        .min()
+        .copied();
+
+    // (account, date, amount)
+    let mut postings: Vec<(String, NaiveDate, Money)> = vec![];
+    for t in ledger.transactions.iter() {
+        for p in t.postings.borrow().iter() {
+            if matches!(end, Some(end) if p.date >= end) {
+                continue;
+            }
+            if !filter::filter(&unbounded, &node, t, p, &ledger.commodities)? {
+                continue;
+            }
+            let parts: Vec<&str> = p.account.get_name().split(':').collect();
+            let account = match options.depth {
+                Some(depth) => parts[..depth.clamp(1, parts.len())].join(":"),
+                None => p.account.get_name().to_string(),
+            };
+            let money = match options.exchange.is_empty() {
+                true => p.amount.clone().unwrap(),
+                false => p.exchange_amount(),
+            };
+            postings.push((account, p.date, money));
+        }
+    }
+    let shown: Vec<NaiveDate> = postings
+        .iter()
+        .map(|x| x.1)
+        .filter(|x| !matches!(begin, Some(begin) if *x < begin))
+        .collect();
+    if shown.is_empty() {
+        return Ok(());
+    }
+    let first = begin.unwrap_or_else(|| *shown.iter().min().unwrap());
+    let last = match end {
+        Some(end) => end.pred_opt().unwrap(),
+        None => *shown.iter().max().unwrap(),
+    };
+    let year_before = first
+        .checked_sub_months(Months::new(HISTORY as u32))
+        .unwrap();
+    let months = report_columns(year_before, last, Some(Frequency::Monthly));
+
+    let mut balances: BTreeMap<String, Vec<Balance>> = BTreeMap::new();
+    for (account, date, money) in postings.iter() {
+        let i = match months.iter().position(|x| (*date >= x.0) & (*date <= x.1)) {
+            Some(i) => i,
+            None => continue,
+        };
+        let account_balances = balances
+            .entry(account.clone())
+            .or_insert_with(|| vec![Balance::new(); months.len()]);
+        account_balances[i] = account_balances[i].clone() + Balance::from(money.clone());
+    }
+    if let Some(currency_string) = options.exchange.first() {
+        let currency = ledger.commodities.get(currency_string)?;
+        for (i, (_, month_end)) in months.iter().enumerate() {
+            let multipliers = conversion(
+                currency.clone(),
+                month_end.succ_opt().unwrap(),
+                &ledger.prices,
+            );
+            for account_balances in balances.values_mut() {
+                account_balances[i] =
+                    convert_balance(&account_balances[i], &multipliers, currency)?;
+            }
+        }
+    }
+    let mut trends: BTreeMap<String, Trend> = BTreeMap::new();
+    for (account, account_balances) in balances.iter() {
+        let error = || MixedCommoditiesError(account.clone());
+        let mut currency: Option<Rc<Currency>> = None;
+        let mut values = vec![];
+        for balance in account_balances.iter() {
+            match balance.to_money().map_err(|_| error())? {
+                Money::Zero => values.push(BigRational::zero()),
+                Money::Money {
+                    currency: c,
+                    amount,
+                } => {
+                    if currency.as_ref().is_some_and(|x| x != &c) {
+                        return Err(Box::new(error()));
+                    }
+                    currency = Some(c);
+                    values.push(amount);
+                }
+            }
+        }
+        // Accounts without postings in the report, only in the year before
+        if values[HISTORY..].iter().all(|x| x.is_zero()) {
+            continue;
+        }
+        trends.insert(account.clone(), Trend { currency, values });
+    }
+    if trends.is_empty() {
+        return Ok(());
+    }
+
+    let deviations = BigRational::from_float(deviations).unwrap_or_else(BigRational::zero);
+    let labels: Vec<String> = months[HISTORY..]
+        .iter()
+        .map(|x| format!("{}", x.0.format("%Y-%m")))
+        .collect();
+    match heatmap {
+        true => print_heatmap(&trends, &labels, &deviations),
+        false => print_table(&trends, &labels, &deviations),
+    }
+    Ok(())
+}
+
+/// A column for every month, with the amounts
+fn print_table(trends: &BTreeMap<String, Trend>, labels: &[String], deviations: &BigRational) {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    let mut titles = vec![Cell::new("Account")];
+    for label in labels.iter() {
+        titles.push(Cell::new(label).style_spec("r"));
+    }
+    titles.push(Cell::new("MoM").style_spec("r"));
+    titles.push(Cell::new("YoY").style_spec("r"));
+    table.set_titles(Row::new(titles));
+    let mut any_unusual = false;
+    for (account, trend) in trends.iter() {
+        let values = &trend.values[HISTORY..];
+        let mut row = vec![Cell::new(account)];
+        for (value, is_unusual) in values.iter().zip(unusual(&trend.values, deviations)) {
+            let text = match &trend.currency {
+                Some(currency) => format!("{}", Money::from((currency.clone(), value.clone()))),
+                None => "0".to_string(),
+            };
+            any_unusual |= is_unusual;
+            row.push(match is_unusual {
+                true => Cell::new(&format!("{} !", text)).style_spec("rFr"),
+                false => Cell::new(&format!("{}  ", text)).style_spec("r"),
+            });
+        }
+        let (month, year) = changes(&trend.values);
+        row.push(Cell::new(&percentage(month)).style_spec("r"));
+        row.push(Cell::new(&percentage(year)).style_spec("r"));
+        table.add_row(Row::new(row));
+    }
+    table.printstd();
+    if any_unusual {
+        println!("! unusual month for the account");
+    }
+}
+
+/// A character for every month, darker the larger the amount is
+fn print_heatmap(trends: &BTreeMap<String, Trend>, labels: &[String], deviations: &BigRational) {
+    let width = trends.keys().map(|x| x.chars().count()).max().unwrap();
+    println!("{} to {}", labels[0], labels[labels.len() - 1]);
+    println!(
+        "{:width$}  {}  {:>8}{:>8}",
+        "",
+        labels
+            .iter()
+            .map(|x| x[5..].to_string())
+            .collect::<Vec<String>>()
+            .join(" "),
+        "MoM",
+        "YoY",
+        width = width
+    );
+    for (account, trend) in trends.iter() {
+        let values = &trend.values[HISTORY..];
+        let cells: Vec<String> = values
+            .iter()
+            .zip(unusual(&trend.values, deviations))
+            .map(|(value, is_unusual)| {
+                let shade = SHADES[shade(value, values)];
+                format!("{}{}", shade, if is_unusual { '!' } else { shade })
+            })
+            .collect();
+        let (month, year) = changes(&trend.values);
+        println!(
+            "{:width$}  {}  {:>8}{:>8}",
+            account,
+            cells.join(" "),
+            percentage(month),
+            percentage(year),
+            width = width
+        );
+    }
+}
+
+/// The months after the history that are further than `deviations` standard deviations from
+/// the mean of the months before them
+///
+/// The history of a month is the year before it, from the first month with postings of the
+/// account. The squares of both sides are compared, so no square root is needed.
+fn unusual(values: &[BigRational], deviations: &BigRational) -> Vec<bool> {
+    let start = values
+        .iter()
+        .position(|x| !x.is_zero())
+        .unwrap_or(values.len());
+    (HISTORY..values.len())
+        .map(|i| {
+            let history = &values[(i - HISTORY).max(start).min(i)..i];
+            if history.len() < MIN_HISTORY {
+                return false;
+            }
+            let n = BigRational::from_integer(BigInt::from(history.len()));
+            let mean: BigRational = history.iter().sum::<BigRational>() / &n;
+            let squares = |x: &BigRational| (x - &mean) * (x - &mean);
+            let variance: BigRational = history.iter().map(squares).sum::<BigRational>() / &n;
+            squares(&values[i]) > deviations * deviations * variance
+        })
+        .collect()
+}
+
+/// The index in the shades of a month, compared to the largest month
+fn shade(value: &BigRational, values: &[BigRational]) -> usize {
+    let max = values.iter().map(|x| x.abs()).max().unwrap();
+    match max.is_zero() {
+        true => 0,
+        false => (value.abs() / max * BigInt::from(SHADES.len() - 1))
+            .ceil()
+            .to_integer()
+            .to_usize()
+            .unwrap(),
+    }
+}
+
+/// The relative change of the last month from the month before and from a year before
+fn changes(values: &[BigRational]) -> (Option<f64>, Option<f64>) {
+    let last = values.len() - 1;
+    let change = |previous: &BigRational| match previous.is_zero() {
+        true => None,
+        false => ((&values[last] - previous) / previous.abs()).to_f64(),
+    };
+    (change(&values[last - 1]), change(&values[last - HISTORY]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(x: i64) -> BigRational {
+        BigRational::from_integer(BigInt::from(x))
+    }
+
+    #[test]
+    fn unusual_months() {
+        let mut values: Vec<BigRational> = (0..24).map(|_| n(100)).collect();
+        assert!(unusual(&values, &n(2)).iter().all(|x| !x));
+        // Against a steady history any change is unusual, the month after it is not
+        values[HISTORY + 5] = n(500);
+        let flags = unusual(&values, &n(2));
+        assert_eq!(flags.len(), 12);
+        assert!(flags[5] & (flags.iter().filter(|x| **x).count() == 1));
+        assert_eq!(shade(&n(100), &values), 1);
+        assert_eq!(shade(&n(500), &values), 4);
+        assert_eq!(shade(&n(0), &values), 0);
+
+        // The months before the first postings are not history
+        let mut values: Vec<BigRational> = (0..24).map(|_| n(0)).collect();
+        for value in values[HISTORY - 1..].iter_mut() {
+            *value = n(100);
+        }
+        values[HISTORY + 1] = n(500);
+        assert!(unusual(&values, &n(2)).iter().all(|x| !x));
+    }
+
+    #[test]
+    fn month_and_year_changes() {
+        let mut values: Vec<BigRational> = (0..13).map(|_| n(100)).collect();
+        values[11] = n(0);
+        values[12] = n(120);
+        assert_eq!(changes(&values), (None, Some(0.2)));
+    }
+}
@@ -4,6 +4,8 @@ use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
 
+use chrono::NaiveDate;
+
 use crate::models::{Balance, Currency};
 
 #[derive(Debug)]
@@ -42,6 +44,144 @@ impl Display for TimeParseError {
     }
 }
 #[derive(Debug)]
+pub struct InvalidFormatError(pub String);
+impl Error for InvalidFormatError {}
+impl Display for InvalidFormatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", "Invalid format:".red(), self.0)
+    }
+}
+#[derive(Debug)]
+pub struct InvalidExpressionError(pub String);
+impl Error for InvalidExpressionError {}
+impl Display for InvalidExpressionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", "Invalid expression:".red(), self.0)
+    }
+}
+#[derive(Debug)]
+pub struct InvalidDisplayError(pub String);
+impl Error for InvalidDisplayError {}
+impl Display for InvalidDisplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} is not a boolean",
+            "Invalid display expression:".red(),
+            self.0
+        )
+    }
+}
+#[derive(Debug)]
+pub enum LoanError {
+    InvalidPrincipal(String),
+    InvalidRate(String),
+    NoPayments,
+}
+impl Error for LoanError {}
+impl Display for LoanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoanError::InvalidPrincipal(principal) => {
+                write!(f, "{} {}", "Invalid principal:".red(), principal)
+            }
+            LoanError::InvalidRate(rate) => write!(f, "{} {}", "Invalid rate:".red(), rate),
+            LoanError::NoPayments => {
+                write!(f, "{}", "The term must be at least one payment".red())
+            }
+        }
+    }
+}
+#[derive(Debug)]
+pub struct InvalidPeriodError(pub String);
+impl Error for InvalidPeriodError {}
+impl Display for InvalidPeriodError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", "Invalid period:".red(), self.0)
+    }
+}
+#[derive(Debug)]
+pub enum GainsError {
+    /// A commodity bought or sold in more than one currency
+    DifferentCurrencies(String),
+    /// More is sold than what is held
+    NotEnoughHeld(String, NaiveDate),
+    /// A sale without a cost whose price can't be told from its transaction
+    NoPrice(String, String, NaiveDate),
+}
+impl Error for GainsError {}
+impl Display for GainsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GainsError::DifferentCurrencies(commodity) => write!(
+                f,
+                "{} {} is bought and sold in different currencies",
+                "Could not compute the gains:".red(),
+                commodity
+            ),
+            GainsError::NotEnoughHeld(commodity, date) => write!(
+                f,
+                "{} more {} is sold on {} than what is held",
+                "Could not compute the gains:".red(),
+                commodity,
+                date
+            ),
+            GainsError::NoPrice(commodity, account, date) => write!(
+                f,
+                "{} the sale of {} in {} on {} has no price, add one with @",
+                "Could not compute the gains:".red(),
+                commodity,
+                account,
+                date
+            ),
+        }
+    }
+}
+#[derive(Debug)]
+pub enum DepreciationError {
+    InvalidSchedule(String),
+    /// The depreciated posting has no amount of its own
+    MissingAmount(String),
+}
+impl Error for DepreciationError {}
+impl Display for DepreciationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DepreciationError::InvalidSchedule(schedule) => {
+                write!(f, "{} {}", "Invalid depreciation schedule:".red(), schedule)
+            }
+            DepreciationError::MissingAmount(account) => write!(
+                f,
+                "{} the posting to {} needs an amount",
+                "Could not depreciate:".red(),
+                account
+            ),
+        }
+    }
+}
+#[derive(Debug)]
+pub enum ReconcileError {
+    InvalidStatementBalance(String),
+    /// The posting does not come from a journal file that can be edited
+    UnknownLocation(String),
+}
+impl Error for ReconcileError {}
+impl Display for ReconcileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReconcileError::InvalidStatementBalance(balance) => {
+                write!(f, "{} {}", "Invalid statement balance:".red(), balance)
+            }
+            ReconcileError::UnknownLocation(account) => write!(
+                f,
+                "{} a posting to {} is not in a journal file",
+                "Could not mark the postings:".red(),
+                account
+            ),
+        }
+    }
+}
+#[derive(Debug)]
 pub enum ReportError {
     CurrencyConversionError(Currency, Currency),
 }
@@ -59,6 +199,10 @@ impl Display for ReportError {
 pub enum LedgerError {
     AliasNotInList(String),
     TooManyEmptyPostings(usize),
+    ExpressionIsNotMoney(String),
+    MixedCommodities(String),
+    DivisionByZero,
+    MissingAmount(String),
 }
 impl Error for LedgerError {}
 impl Display for LedgerError {
@@ -68,9 +212,61 @@ impl Display for LedgerError {
             LedgerError::TooManyEmptyPostings(x) => {
                 write!(f, "{} {}", "Too many empty postings:".red(), x)
             }
+            LedgerError::ExpressionIsNotMoney(x) => {
+                write!(
+                    f,
+                    "{} {}",
+                    "Expression does not evaluate to money:".red(),
+                    x
+                )
+            }
+            LedgerError::MixedCommodities(x) => {
+                write!(
+                    f,
+                    "{} {}",
+                    "Can't add or subtract different commodities:".red(),
+                    x
+                )
+            }
+            LedgerError::DivisionByZero => {
+                write!(f, "{}", "Division by zero in an expression".red())
+            }
+            LedgerError::MissingAmount(x) => write!(
+                f,
+                "{} {}",
+                "The posting has no amount for the expression:".red(),
+                x
+            ),
         }
     }
 }
+/// A report that needs a single commodity found more than one
+#[derive(Debug)]
+pub struct MixedCommoditiesError(pub String);
+impl Error for MixedCommoditiesError {}
+impl Display for MixedCommoditiesError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} has more than one commodity, convert it to one with -X",
+            "Mixed commodities:".red(),
+            self.0
+        )
+    }
+}
+#[derive(Debug)]
+pub struct UnknownAccountTypeError(pub String);
+impl Error for UnknownAccountTypeError {}
+impl Display for UnknownAccountTypeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}, the types are A, L, E, R, X and C or their names",
+            "Unknown account type:".red(),
+            self.0
+        )
+    }
+}
 #[derive(Debug)]
 pub enum BalanceError {
     TransactionIsNotBalanced,
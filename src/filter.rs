@@ -1,5 +1,5 @@
-use crate::error::GenericError;
-use crate::models::{Currency, Posting, PostingType, Transaction};
+use crate::error::{GenericError, UnknownAccountTypeError};
+use crate::models::{AccountType, Cleared, Currency, Posting, PostingType, Transaction};
 use crate::parser::value_expr::{eval, EvalResult, Node};
 use crate::{CommonOpts, List};
 use colored::Colorize;
@@ -25,6 +25,18 @@ pub fn filter(
         }
     }
 
+    // Check the status, several of them can be selected
+    if options.cleared | options.pending | options.uncleared {
+        let selected = match posting.cleared {
+            Cleared::Cleared => options.cleared,
+            Cleared::Pending => options.pending,
+            Cleared::Unknown | Cleared::NotCleared => options.uncleared,
+        };
+        if !selected {
+            return Ok(false);
+        }
+    }
+
     // Check for dates at the posting level
     if let Some(date) = options.end {
         if posting.date >= date {
@@ -36,6 +48,11 @@ pub fn filter(
             return Ok(false);
         }
     }
+    if let Some((begin, end)) = options.period {
+        if (posting.date < begin) | (posting.date >= end) {
+            return Ok(false);
+        }
+    }
     match node {
         Some(x) => filter_expression(x, posting, transaction, commodities, &mut HashMap::new()),
         None => Ok(true),
@@ -49,7 +66,7 @@ pub fn filter_expression(
     commodities: &List<Currency>,
     regexes: &mut HashMap<String, Regex>,
 ) -> Result<bool, Box<dyn std::error::Error>> {
-    let result = eval(predicate, posting, transaction, commodities, regexes);
+    let result = eval(predicate, posting, transaction, commodities, regexes)?;
     match result {
         EvalResult::Boolean(b) => Ok(b),
         _ => Err(Box::new(GenericError {
@@ -70,10 +87,22 @@ pub fn filter_expression(
 /// ```rust
 /// # use dinero::filter::preprocess_query;
 /// let params:Vec<String> = vec!["@payee", "savings" , "and", "checking", "and", "expr", "/aeiou/"].iter().map(|x| x.to_string()).collect();
-/// let processed = preprocess_query(&params, &false);
-/// assert_eq!(processed, "((payee =~ /(?i)payee/) or (account =~ /(?i)savings/) and (account =~ /(?i)checking/) and (/aeiou/))")
+/// let processed = preprocess_query(&params, &false).unwrap();
+/// assert_eq!(processed, "((payee =~ /(?i)payee/) or (account =~ /(?i)savings/) and (account =~ /(?i)checking/) and (/aeiou/))");
+///
+/// let params:Vec<String> = vec!["type:AL"].iter().map(|x| x.to_string()).collect();
+/// let processed = preprocess_query(&params, &false).unwrap();
+/// assert_eq!(processed, "((account_type =~ /^[ALC]$/))");
+///
+/// let params:Vec<String> = vec!["type:Asset"].iter().map(|x| x.to_string()).collect();
+/// let processed = preprocess_query(&params, &false).unwrap();
+/// assert_eq!(processed, "((account_type =~ /^[AC]$/))");
+/// assert!(preprocess_query(&["type:AY".to_string()], &false).is_err());
 /// ```
-pub fn preprocess_query(query: &[String], related: &bool) -> String {
+pub fn preprocess_query(
+    query: &[String],
+    related: &bool,
+) -> Result<String, UnknownAccountTypeError> {
     let mut expression = String::new();
     let mut and = false;
     let mut first = true;
@@ -105,6 +134,10 @@ pub fn preprocess_query(query: &[String], related: &bool) -> String {
         expression.push_str(join_term);
         if expr {
             expression.push_str(term);
+        } else if let Some(types) = term.strip_prefix("type:") {
+            expression.push_str("account_type =~ /^[");
+            expression.push_str(&account_type_codes(types)?);
+            expression.push_str("]$/");
         } else if let Some(c) = term.chars().next() {
             match c {
                 '@' => {
@@ -135,8 +168,33 @@ pub fn preprocess_query(query: &[String], related: &bool) -> String {
     }
 
     if *related {
-        format!("(any({}) and not({}))", expression, expression)
+        Ok(format!("(any({}) and not({}))", expression, expression))
     } else {
-        format!("({})", expression)
+        Ok(format!("({})", expression))
+    }
+}
+
+/// The codes of the account types of a ```type:``` term, like ```AL```, ```Asset``` or
+/// ```asset,liability```. Cash accounts are also assets.
+fn account_type_codes(types: &str) -> Result<String, UnknownAccountTypeError> {
+    let names: Vec<String> = match types.contains(',') | (AccountType::parse(types).is_some()) {
+        true => types.split(',').map(|x| x.to_string()).collect(),
+        false => types.chars().map(|x| x.to_string()).collect(),
+    };
+    let mut codes = String::new();
+    for name in names.iter() {
+        let account_type =
+            AccountType::parse(name).ok_or_else(|| UnknownAccountTypeError(name.clone()))?;
+        let code = account_type.to_string();
+        if !codes.contains(&code) {
+            codes.push_str(&code);
+        }
+    }
+    if codes.contains('A') & !codes.contains('C') {
+        codes.push('C');
+    }
+    match codes.is_empty() {
+        true => Err(UnknownAccountTypeError(types.to_string())),
+        false => Ok(codes),
     }
 }
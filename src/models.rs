@@ -5,24 +5,29 @@ use std::{
     path::PathBuf,
 };
 
-pub use account::Account;
+pub use account::{Account, AccountType};
 pub use balance::Balance;
 pub use comment::Comment;
 pub use currency::{Currency, CurrencyDisplayFormat, DigitGrouping};
-pub use money::Money;
+pub use depreciation::Depreciation;
+pub use money::{plain_decimal, Money};
 pub use payee::Payee;
+pub use periodic::Periodicity;
 pub use price::conversion;
 pub use price::{Price, PriceType};
 pub use transaction::{
-    Cleared, Posting, PostingOrigin, PostingType, Transaction, TransactionStatus, TransactionType,
+    Cleared, Cost, Posting, PostingOrigin, PostingType, SourceLocation, Transaction,
+    TransactionStatus, TransactionType,
 };
 
+use crate::app::period_parser;
+use crate::models::depreciation::depreciation_transactions;
 use crate::parser::value_expr::build_root_node_from_expression;
+use crate::parser::Tokenizer;
 use crate::parser::{tokenizers, value_expr};
 use crate::List;
 use crate::{error::EmptyLedgerFileError, parser::ParsedLedger};
 use crate::{filter::filter_expression, CommonOpts};
-use crate::{models::transaction::Cost, parser::Tokenizer};
 use num::BigInt;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -31,8 +36,10 @@ mod account;
 mod balance;
 mod comment;
 mod currency;
+mod depreciation;
 mod money;
 mod payee;
+mod periodic;
 mod price;
 mod transaction;
 
@@ -43,16 +50,21 @@ pub struct Ledger {
     pub(crate) transactions: Vec<Transaction<Posting>>,
     pub(crate) prices: Vec<Price>,
     pub(crate) payees: List<Payee>,
+    pub(crate) tags: Vec<Tag>,
     pub(crate) files: Vec<PathBuf>,
 }
 
 impl TryFrom<&CommonOpts> for Ledger {
     type Error = Box<dyn std::error::Error>;
     fn try_from(options: &CommonOpts) -> Result<Self, Self::Error> {
-        // Get the options
-        let path: PathBuf = options.input_file.clone();
-        let mut tokenizer: Tokenizer = Tokenizer::try_from(&path)?;
-        let items = tokenizer.tokenize(options);
+        // Parse every input file, merging them as if they were included
+        let mut items = ParsedLedger::new();
+        for path in options.input_files.iter() {
+            let mut tokenizer: Tokenizer = Tokenizer::try_from(path)?;
+            let mut new_items =
+                tokenizer.tokenize_with_currencies(options, Some(&items.commodities));
+            items.append(&mut new_items);
+        }
         if items.is_empty() {
             Err(Box::new(EmptyLedgerFileError))
         } else {
@@ -74,6 +86,7 @@ impl Ledger {
 impl ParsedLedger {
     /// Creates a proper ledger from a parsed ledger
     ///
+    /// 0. Expand the depreciation schedules into transactions
     /// 1. Create the lists of accounts, commodities and payees
     /// 2. Load the commodity prices
     /// 3. Balance the transactions by filling in missing amounts (this previously sorts the transactions by date)
@@ -86,8 +99,19 @@ impl ParsedLedger {
         let mut account_strs = HashSet::<String>::new();
         let mut payee_strs = HashSet::<String>::new();
 
-        // 1. Populate the directive lists
+        // 0. Depreciation of fixed assets
+        let mut depreciations = vec![];
         for transaction in self.transactions.iter() {
+            if transaction.transaction_type == TransactionType::Real {
+                depreciations.append(&mut depreciation_transactions(
+                    transaction,
+                    &self.commodities,
+                )?);
+            }
+        }
+
+        // 1. Populate the directive lists
+        for transaction in self.transactions.iter().chain(depreciations.iter()) {
             for p in transaction.postings.borrow().iter() {
                 account_strs.insert(p.account.clone());
                 if let Some(payee) = p.payee.clone() {
@@ -135,6 +159,31 @@ impl ParsedLedger {
             }
         }
 
+        // Account types are inherited from the parent accounts or inferred from the name
+        let mut account_types = Vec::new();
+        for (_, account) in self.accounts.iter() {
+            if account.account_type.is_some() {
+                continue;
+            }
+            let parts: Vec<&str> = account.get_name().split(':').collect();
+            let inherited = (1..parts.len()).rev().find_map(|depth| {
+                match self.accounts.get(parts[0..depth].join(":").as_str()) {
+                    Ok(parent) => parent.account_type,
+                    Err(_) => None,
+                }
+            });
+            if let Some(account_type) = inherited.or_else(|| AccountType::infer(account.get_name()))
+            {
+                let mut typed = account.as_ref().clone();
+                typed.account_type = Some(account_type);
+                account_types.push(typed);
+            }
+        }
+        for account in account_types {
+            self.accounts.remove(&account);
+            self.accounts.insert(account);
+        }
+
         // Payees
         let payees_copy = self.payees.clone();
         for alias in payee_strs {
@@ -189,14 +238,61 @@ impl ParsedLedger {
         //
         let mut transactions = Vec::new();
         let mut automated_transactions = Vec::new();
+        let mut periodic_transactions = Vec::new();
 
         for parsed in self.transactions.iter() {
             let mut transformer = self._transaction_to_ledger(parsed)?;
             // (mut t, mut auto, mut new_prices)
             transactions.append(&mut transformer.ledger_transactions);
             automated_transactions.append(&mut transformer.raw_transactions);
+            periodic_transactions.append(&mut transformer.periodic_transactions);
             prices.append(&mut transformer.prices);
         }
+        for parsed in depreciations.iter() {
+            for t in self._transaction_to_ledger(parsed)?.ledger_transactions {
+                for p in t.postings.borrow_mut().iter_mut() {
+                    p.origin = PostingOrigin::Automated;
+                }
+                transactions.push(t);
+            }
+        }
+
+        // The forecast starts after the last real transaction
+        if let Some(forecast) = &options.forecast {
+            let forecast = match forecast {
+                Some(period) => Some(period_parser(period)?),
+                None => None,
+            };
+            if let Some(last) = transactions.iter().filter_map(|t| t.date).max() {
+                let begin = match forecast {
+                    Some((begin, _)) => begin.max(last.succ_opt().unwrap()),
+                    None => last.succ_opt().unwrap(),
+                };
+                let end = match forecast {
+                    Some((_, end)) => end,
+                    None => begin
+                        .with_year(begin.year() + 1)
+                        .unwrap_or(begin + Duration::days(365)),
+                };
+                for periodic in periodic_transactions.iter() {
+                    let period = Periodicity::try_from(periodic.period.as_ref().unwrap().as_str())?;
+                    for date in period.dates(begin, end) {
+                        let mut forecast = periodic.clone();
+                        forecast.transaction_type = TransactionType::Real;
+                        forecast.date = Some(date);
+                        for p in forecast.postings.get_mut().iter_mut() {
+                            p.date = Some(date);
+                        }
+                        for t in self._transaction_to_ledger(&forecast)?.ledger_transactions {
+                            for p in t.postings.borrow_mut().iter_mut() {
+                                p.origin = PostingOrigin::Periodic;
+                            }
+                            transactions.push(t);
+                        }
+                    }
+                }
+            }
+        }
 
         // Now sort the transactions vector by date
         transactions.sort_by(|a, b| a.date.unwrap().cmp(&b.date.unwrap()));
@@ -231,7 +327,7 @@ impl ParsedLedger {
             let mut root_nodes = HashMap::new();
             let mut regexes = HashMap::new();
             for automated in automated_transactions.iter_mut() {
-                let query = automated.get_filter_query();
+                let query = automated.get_filter_query()?;
                 let node = build_root_node_from_expression(query.as_str(), &mut regexes);
                 root_nodes.insert(query, node);
             }
@@ -244,7 +340,7 @@ impl ParsedLedger {
                         if p.origin != PostingOrigin::FromTransaction {
                             continue;
                         }
-                        let node = root_nodes.get(automated.get_filter_query().as_str());
+                        let node = root_nodes.get(automated.get_filter_query()?.as_str());
                         if filter_expression(
                             node.unwrap(), // automated.get_filter_query().as_str(),
                             p,
@@ -283,7 +379,7 @@ impl ParsedLedger {
                                         t,
                                         &mut self.commodities,
                                         &mut regexes,
-                                    )),
+                                    )?),
                                     Some(alias) => {
                                         if alias.is_empty() {
                                             Some(Money::from((
@@ -312,12 +408,16 @@ impl ParsedLedger {
                                     amount: money,
                                     balance: None,
                                     cost: None,
+                                    lot_price: None,
+                                    lot_fixated: false,
                                     kind: auto_posting.kind,
                                     comments: vec![],
                                     tags: RefCell::new(vec![]),
                                     payee,
                                     transaction: RefCell::new(Rc::downgrade(&Rc::new(t.clone()))),
                                     origin: PostingOrigin::Automated,
+                                    cleared: t.cleared,
+                                    source: None,
                                 };
 
                                 extra_postings.push(posting);
@@ -366,6 +466,7 @@ impl ParsedLedger {
             transactions,
             prices,
             payees: self.payees,
+            tags: self.tags,
             files: self.files,
         })
     }
@@ -375,8 +476,10 @@ impl ParsedLedger {
         parsed: &Transaction<tokenizers::transaction::RawPosting>,
     ) -> Result<TransactionTransformer, Box<dyn std::error::Error>> {
         let mut automated_transactions = vec![];
+        let mut periodic_transactions = vec![];
         let mut prices = vec![];
         let mut transactions = vec![];
+        let mut regexes = HashMap::new();
         match parsed.transaction_type {
             TransactionType::Real => {
                 let mut transaction = Transaction::<Posting>::new(TransactionType::Real);
@@ -422,6 +525,11 @@ impl ParsedLedger {
                         PostingOrigin::FromTransaction,
                         p.date.unwrap(),
                     );
+                    posting.cleared = match p.cleared {
+                        Cleared::Unknown => transaction.cleared,
+                        x => x,
+                    };
+                    posting.source = p.source.clone();
                     posting.tags = RefCell::new(transaction.tags.clone());
                     for comment in p.comments.iter() {
                         posting.tags.borrow_mut().append(&mut comment.get_tags());
@@ -433,17 +541,48 @@ impl ParsedLedger {
                             self.commodities.get(c.as_str()).unwrap().clone(),
                             p.money_amount.clone().unwrap(),
                         )));
+                    } else if let Some(expression) = &p.amount_expr {
+                        posting.amount = Some(value_expr::eval_money_expression(
+                            expression,
+                            &posting,
+                            &transaction,
+                            &self.commodities,
+                            &mut regexes,
+                        )?);
                     }
-                    if let Some(c) = &p.cost_currency {
-                        let posting_currency = self
-                            .commodities
-                            .get(p.money_currency.as_ref().unwrap().as_str())
-                            .unwrap();
-                        let amount = Money::from((
+                    let cost = if let Some(c) = &p.cost_currency {
+                        Some(Money::from((
                             self.commodities.get(c.as_str()).unwrap().clone(),
                             p.cost_amount.clone().unwrap(),
-                        ));
-                        posting.cost = match p.cost_type.as_ref().unwrap() {
+                        )))
+                    } else if let Some(expression) = &p.cost_expr {
+                        Some(value_expr::eval_money_expression(
+                            expression,
+                            &posting,
+                            &transaction,
+                            &self.commodities,
+                            &mut regexes,
+                        )?)
+                    } else {
+                        None
+                    };
+                    if let Some(c) = &p.lot_currency {
+                        posting.lot_price = Some(Money::from((
+                            self.commodities.get(c.as_str()).unwrap().clone(),
+                            p.lot_amount.clone().unwrap(),
+                        )));
+                        posting.lot_fixated = p.lot_fixated;
+                    }
+                    // A lot price is the cost unless there is an explicit one
+                    let explicit_cost = cost.is_some();
+                    let cost_type = match cost {
+                        Some(_) => p.cost_type.unwrap(),
+                        None => PriceType::PerUnit,
+                    };
+                    if let Some(amount) = cost.or_else(|| posting.lot_price.clone()) {
+                        let posting_currency =
+                            posting.amount.as_ref().unwrap().get_commodity().unwrap();
+                        posting.cost = match cost_type {
                             PriceType::Total => Some(Cost::Total {
                                 amount: amount.clone(),
                             }),
@@ -451,20 +590,26 @@ impl ParsedLedger {
                                 amount: amount.clone(),
                             }),
                         };
-                        prices.push(Price::new(
-                            transaction.date.unwrap(),
-                            posting_currency.clone(),
-                            Money::Money {
-                                amount: p.cost_amount.clone().unwrap()
-                                    / match p.cost_type.as_ref().unwrap() {
-                                        PriceType::Total => {
-                                            posting.amount.as_ref().unwrap().get_amount()
-                                        }
-                                        PriceType::PerUnit => BigRational::from(BigInt::from(1)),
-                                    },
-                                currency: amount.get_commodity().unwrap().clone(),
-                            },
-                        ))
+                        // Virtual costs and fixated lot prices are not market prices, the
+                        // explicit cost of a fixated lot is
+                        if !p.virtual_cost & (explicit_cost | !p.lot_fixated) {
+                            prices.push(Price::new(
+                                transaction.date.unwrap(),
+                                posting_currency,
+                                Money::Money {
+                                    amount: amount.get_amount()
+                                        / match cost_type {
+                                            PriceType::Total => {
+                                                posting.amount.as_ref().unwrap().get_amount()
+                                            }
+                                            PriceType::PerUnit => {
+                                                BigRational::from(BigInt::from(1))
+                                            }
+                                        },
+                                    currency: amount.get_commodity().unwrap(),
+                                },
+                            ));
+                        }
                     }
                     if let Some(c) = &p.balance_currency {
                         posting.balance = Some(Money::from((
@@ -485,12 +630,14 @@ impl ParsedLedger {
                 automated_transactions.push(parsed.clone());
             }
             TransactionType::Periodic => {
-                eprintln!("Found periodic transaction. Skipping.");
+                // They are only used for the forecast
+                periodic_transactions.push(parsed.clone());
             }
         }
         Ok(TransactionTransformer {
             ledger_transactions: transactions,
             raw_transactions: automated_transactions,
+            periodic_transactions,
             prices,
         })
     }
@@ -517,6 +664,7 @@ pub trait FromDirective {
 
 #[cfg(test)]
 mod tests {
+    use num::{BigInt, BigRational};
     use structopt::StructOpt;
 
     use crate::{parser::Tokenizer, CommonOpts};
@@ -539,9 +687,121 @@ mod tests {
         assert!(&ledger.payees.get("EstateGuru").is_ok());
         assert!(payee.is_some());
     }
+
+    #[test]
+    fn posting_amount_expressions() {
+        let mut tokenizer = Tokenizer::from(
+            "2021-03-01 * Dinner
+    Expenses:Shared       (84.30 EUR / 3)
+    Expenses:Restaurants  (84.30 EUR * 2 / 3)
+    Assets:Checking account
+2021-03-02 * Shares
+    Assets:Shares         3 ACME @ (100 USD / 3)
+    Assets:Checking account
+"
+            .to_string(),
+        );
+        let options = CommonOpts::from_iter(["", "-f", ""].iter());
+
+        let items = tokenizer.tokenize(&options);
+        let ledger = items.to_ledger(&options).unwrap();
+        let postings = ledger.transactions[0].postings.borrow();
+        assert_eq!(
+            postings[0].amount.as_ref().unwrap().get_amount(),
+            BigRational::new(BigInt::from(843), BigInt::from(30))
+        );
+        assert_eq!(
+            postings[2].amount.as_ref().unwrap().get_amount(),
+            BigRational::new(BigInt::from(-843), BigInt::from(10))
+        );
+        let postings = ledger.transactions[1].postings.borrow();
+        assert_eq!(
+            postings[1].amount.as_ref().unwrap().get_amount(),
+            BigRational::from(BigInt::from(-100))
+        );
+    }
+
+    #[test]
+    fn virtual_costs_and_lot_prices() {
+        let mut tokenizer = Tokenizer::from(
+            "2021-03-01 * Buy
+    Assets:Shares         10 ACME {=50 USD}
+    Assets:Checking account
+2021-03-02 * Sell
+    Assets:Shares         -5 ACME {=50 USD} @ 60 USD
+    Assets:Checking account
+2021-03-03 * Exchange
+    Assets:Cash           100 EUR (@) 1.2 USD
+    Assets:Checking account
+2021-03-04 * Exchange
+    Assets:Cash           100 EUR (@@) 110 USD
+    Assets:Checking account
+"
+            .to_string(),
+        );
+        let options = CommonOpts::from_iter(["", "-f", ""].iter());
+
+        let items = tokenizer.tokenize(&options);
+        let ledger = items.to_ledger(&options).unwrap();
+        // Neither virtual costs nor fixated lot prices are market prices, the cost of the sale is
+        assert_eq!(ledger.prices.len(), 1);
+        assert_eq!(
+            ledger.prices[0].get_price().get_amount(),
+            BigRational::from(BigInt::from(60))
+        );
+        let expected = [-500, 300, -120, -110];
+        for (transaction, amount) in ledger.transactions.iter().zip(expected.iter()) {
+            assert_eq!(
+                transaction.postings.borrow()[1]
+                    .amount
+                    .as_ref()
+                    .unwrap()
+                    .get_amount(),
+                BigRational::from(BigInt::from(*amount))
+            );
+        }
+    }
+
+    #[test]
+    fn fixated_lot_price_and_cost() {
+        let mut tokenizer = Tokenizer::from(
+            "2021-03-02 * Sell
+    Assets:Shares         -5 ACME {=50 USD} @ 60 USD
+    Assets:Checking account
+2021-03-03 * Sell
+    Assets:Shares         -5 ACME {50 USD} @ 60 USD
+    Assets:Checking account
+"
+            .to_string(),
+        );
+        let options = CommonOpts::from_iter(["", "-f", ""].iter());
+
+        let items = tokenizer.tokenize(&options);
+        let ledger = items.to_ledger(&options).unwrap();
+        // The explicit costs are market prices, with or without a fixated lot price
+        assert_eq!(ledger.prices.len(), 2);
+        for price in ledger.prices.iter() {
+            assert_eq!(
+                price.get_price().get_amount(),
+                BigRational::from(BigInt::from(60))
+            );
+        }
+        for transaction in ledger.transactions.iter() {
+            let postings = transaction.postings.borrow();
+            // The sale is at the cost and the lot keeps its price
+            assert_eq!(
+                postings[1].amount.as_ref().unwrap().get_amount(),
+                BigRational::from(BigInt::from(300))
+            );
+            assert_eq!(
+                postings[0].lot_price.as_ref().unwrap().get_amount(),
+                BigRational::from(BigInt::from(50))
+            );
+        }
+    }
 }
 
-use chrono::NaiveDate;
+use chrono::{Datelike, Duration, NaiveDate};
 
 #[derive(Debug, Clone)]
 pub struct ParsedPrice {
@@ -568,5 +828,6 @@ impl HasName for Tag {
 struct TransactionTransformer {
     ledger_transactions: Vec<Transaction<Posting>>,
     raw_transactions: Vec<Transaction<tokenizers::transaction::RawPosting>>,
+    periodic_transactions: Vec<Transaction<tokenizers::transaction::RawPosting>>,
     prices: Vec<Price>,
 }
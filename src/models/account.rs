@@ -1,4 +1,5 @@
 use crate::models::{FromDirective, HasAliases, HasName, Origin};
+use lazy_static::lazy_static;
 use regex::Regex;
 use std::cell::RefCell;
 use std::collections::hash_map::RandomState;
@@ -20,9 +21,91 @@ pub struct Account {
     pub(crate) assert: Vec<String>,
     pub(crate) payee: Vec<Regex>,
     pub(crate) default: bool,
+    pub(crate) account_type: Option<AccountType>,
     matches: RefCell<HashMap<String, bool>>,
 }
 
+/// The type of an account, as declared with a ```type:``` tag in the account directive
+///
+/// ```ledger
+/// account Assets:Bank  ; type: C
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccountType {
+    Asset,
+    Liability,
+    Equity,
+    Revenue,
+    Expense,
+    /// Cash is an asset that is liquid enough to be part of the cash flow
+    Cash,
+}
+
+impl AccountType {
+    /// Parses both the one letter codes and the full names
+    pub fn parse(account_type: &str) -> Option<AccountType> {
+        match account_type.trim().to_lowercase().as_str() {
+            "a" | "asset" | "assets" => Some(AccountType::Asset),
+            "l" | "liability" | "liabilities" => Some(AccountType::Liability),
+            "e" | "equity" => Some(AccountType::Equity),
+            "r" | "revenue" | "revenues" | "income" => Some(AccountType::Revenue),
+            "x" | "expense" | "expenses" => Some(AccountType::Expense),
+            "c" | "cash" => Some(AccountType::Cash),
+            _ => None,
+        }
+    }
+
+    /// Infers the account type from the account name when it has not been declared
+    pub fn infer(name: &str) -> Option<AccountType> {
+        lazy_static! {
+            static ref RE_CASH: Regex = Regex::new(
+                r"(?i)^assets?(:.+)?:(cash|bank|che(ck|que?)(ing)?|savings?|current)(:|$)"
+            )
+            .unwrap();
+            static ref RE_ASSET: Regex = Regex::new(r"(?i)^assets?(:|$)").unwrap();
+            static ref RE_LIABILITY: Regex =
+                Regex::new(r"(?i)^(debts?|liabilit(y|ies))(:|$)").unwrap();
+            static ref RE_EQUITY: Regex = Regex::new(r"(?i)^equity(:|$)").unwrap();
+            static ref RE_REVENUE: Regex = Regex::new(r"(?i)^(income|revenues?)(:|$)").unwrap();
+            static ref RE_EXPENSE: Regex = Regex::new(r"(?i)^expenses?(:|$)").unwrap();
+        }
+        if RE_CASH.is_match(name) {
+            Some(AccountType::Cash)
+        } else if RE_ASSET.is_match(name) {
+            Some(AccountType::Asset)
+        } else if RE_LIABILITY.is_match(name) {
+            Some(AccountType::Liability)
+        } else if RE_EQUITY.is_match(name) {
+            Some(AccountType::Equity)
+        } else if RE_REVENUE.is_match(name) {
+            Some(AccountType::Revenue)
+        } else if RE_EXPENSE.is_match(name) {
+            Some(AccountType::Expense)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the type is the same or a subtype (cash is an asset)
+    pub fn is(&self, other: AccountType) -> bool {
+        (*self == other) | ((*self == AccountType::Cash) & (other == AccountType::Asset))
+    }
+}
+
+impl Display for AccountType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            AccountType::Asset => "A",
+            AccountType::Liability => "L",
+            AccountType::Equity => "E",
+            AccountType::Revenue => "R",
+            AccountType::Expense => "X",
+            AccountType::Cash => "C",
+        };
+        write!(f, "{}", code)
+    }
+}
+
 impl Account {
     pub fn from_directive(name: String) -> Account {
         Account {
@@ -36,12 +119,16 @@ impl Account {
             assert: vec![],
             payee: vec![],
             default: false,
+            account_type: None,
             matches: RefCell::new(HashMap::new()),
         }
     }
     pub fn is_default(&self) -> bool {
         self.default
     }
+    pub fn get_account_type(&self) -> Option<AccountType> {
+        self.account_type
+    }
     pub fn payees(&self) -> &Vec<Regex> {
         &self.payee
     }
@@ -103,6 +190,7 @@ impl From<&str> for Account {
             assert: vec![],
             payee: vec![],
             default: false,
+            account_type: None,
             matches: RefCell::new(HashMap::new()),
         }
     }
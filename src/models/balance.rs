@@ -1,11 +1,12 @@
 use crate::error::BalanceError;
 use crate::models::{Currency, Money};
+use num::rational::BigRational;
 use std::collections::hash_map::Iter;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::fmt::{Display, Formatter};
-use std::ops::{Add, Neg, Sub};
+use std::ops::{Add, Div, Neg, Sub};
 use std::rc::Rc;
 
 /// Balance is money with several currencies, for example 100 USD and 50 EUR
@@ -163,6 +164,20 @@ impl Sub for Balance {
     }
 }
 
+impl Div<BigRational> for Balance {
+    type Output = Balance;
+
+    fn div(self, rhs: BigRational) -> Self::Output {
+        Balance {
+            balance: self
+                .balance
+                .into_iter()
+                .map(|(k, v)| (k, v / rhs.clone()))
+                .collect(),
+        }
+    }
+}
+
 // Converter
 impl From<Money> for Balance {
     fn from(money: Money) -> Self {
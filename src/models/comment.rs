@@ -39,7 +39,7 @@ impl Comment {
             // the tags
             static ref RE_FLAGS: Regex = Regex::new(r"(:.+:) *$").unwrap();
             // the value
-            static ref RE_VALUE: Regex = Regex::new(" *(.*): *(.*) *$").unwrap();
+            static ref RE_VALUE: Regex = Regex::new("^ *([^:]+): *(.*) *$").unwrap();
         }
         let calculated_tags = *self.calculated_tags.borrow_mut();
         let tags = {
@@ -182,6 +182,15 @@ mod tests {
         assert_eq!(tag.value.unwrap(), "value with spaces".to_string());
     }
     #[test]
+    fn tag_value_colons() {
+        let comment = Comment::from("project: Alpha:Design");
+        let tags = comment.get_tags();
+        assert_eq!(tags.len(), 1, "There should be one tag");
+        let tag = tags[0].clone();
+        assert_eq!(tag.get_name(), "project");
+        assert_eq!(tag.value.unwrap(), "Alpha:Design".to_string());
+    }
+    #[test]
     fn date_in_comment() {
         let comment = Comment::from("  [=2021/03/02]  ");
         let date = comment.get_date().unwrap();
@@ -54,6 +54,8 @@ pub struct Currency {
     note: Option<String>,
     aliases: HashSet<String>,
     pub(crate) format: Option<String>,
+    /// The decimal mark of the amounts, from a declaration like ```commodity 1.000,00 EUR```
+    decimal_mark: Option<char>,
     default: bool,
     pub(crate) display_format: RefCell<CurrencyDisplayFormat>,
 }
@@ -107,6 +109,7 @@ impl Currency {
             note: None,
             aliases: HashSet::new(),
             format: None,
+            decimal_mark: None,
             default: false,
             display_format: RefCell::new(DEFAULT_DISPLAY_FORMAT),
         }
@@ -130,6 +133,17 @@ impl Currency {
     pub fn update_precision(&self, precision: usize) {
         self.display_format.borrow_mut().update_precision(precision);
     }
+    /// The decimals that generated amounts, like loan payments or depreciation, are rounded to:
+    /// those of the commodity or of the amount they come from, and at least cents
+    pub fn rounding_precision(&self, amount_decimals: usize) -> usize {
+        self.get_precision().max(amount_decimals).max(2)
+    }
+    pub fn set_decimal_mark(&mut self, decimal_mark: char) {
+        self.decimal_mark = Some(decimal_mark);
+    }
+    pub fn get_decimal_mark(&self) -> Option<char> {
+        self.decimal_mark
+    }
     pub fn set_format(&self, format: &CurrencyDisplayFormat) {
         let mut current_format = self.display_format.borrow_mut();
         current_format.symbol_placement = format.symbol_placement;
@@ -0,0 +1,157 @@
+//! Depreciation schedules of fixed assets
+//!
+//! A posting tagged with `; depreciate: straight-line 36 months` is written off in equal monthly
+//! parts, starting a month after the purchase. The expense account is `Expenses:Depreciation`
+//! unless another one is given, like `; depreciate: straight-line 3 years to Expenses:Computers`.
+use std::cell::RefCell;
+use std::convert::TryFrom;
+
+use chrono::Months;
+use lazy_static::lazy_static;
+use num::{BigInt, BigRational};
+use regex::Regex;
+
+use crate::error::DepreciationError;
+use crate::models::{Currency, HasName, Transaction, TransactionType};
+use crate::parser::tokenizers::transaction::RawPosting;
+use crate::List;
+
+/// The name of the tag
+const TAG: &str = "depreciate";
+const DEFAULT_ACCOUNT: &str = "Expenses:Depreciation";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Depreciation {
+    months: u32,
+    account: String,
+}
+
+impl TryFrom<&str> for Depreciation {
+    type Error = DepreciationError;
+    fn try_from(schedule: &str) -> Result<Self, Self::Error> {
+        lazy_static! {
+            static ref RE_SCHEDULE: Regex = Regex::new(concat!(
+                r"(?i)^\s*straight-line\s+(?P<n>\d+)\s+(?P<unit>month|year)s?",
+                r"(?:\s+to\s+(?P<account>\S.*?))?\s*$"
+            ))
+            .unwrap();
+        }
+        let error = || DepreciationError::InvalidSchedule(schedule.to_string());
+        let captures = RE_SCHEDULE.captures(schedule).ok_or_else(error)?;
+        let n: u32 = captures["n"].parse().map_err(|_| error())?;
+        let months = match captures["unit"].to_lowercase().as_str() {
+            "year" => 12 * n,
+            _ => n,
+        };
+        if months == 0 {
+            return Err(error());
+        }
+        Ok(Depreciation {
+            months,
+            account: captures
+                .name("account")
+                .map_or(DEFAULT_ACCOUNT, |x| x.as_str())
+                .to_string(),
+        })
+    }
+}
+
+impl Depreciation {
+    /// The monthly amounts, rounded to `decimals`, that add up to exactly `amount`
+    ///
+    /// Every amount is the difference of the rounded cumulative depreciation, so the rounding
+    /// is spread over the schedule instead of piling up in the last month.
+    fn amounts(&self, amount: &BigRational, decimals: usize) -> Vec<BigRational> {
+        let scale = BigRational::from_integer(BigInt::from(10).pow(decimals as u32));
+        let months = BigRational::from_integer(BigInt::from(self.months));
+        let cumulative = |i: u32| {
+            (amount * BigRational::from_integer(BigInt::from(i)) / &months * &scale).round()
+                / &scale
+        };
+        (1..=self.months)
+            .map(|i| cumulative(i) - cumulative(i - 1))
+            .collect()
+    }
+}
+
+/// The depreciation transactions of the postings of a transaction that have the tag
+pub(crate) fn depreciation_transactions(
+    parsed: &Transaction<RawPosting>,
+    commodities: &List<Currency>,
+) -> Result<Vec<Transaction<RawPosting>>, DepreciationError> {
+    let mut transactions = vec![];
+    for p in parsed.postings.borrow().iter() {
+        let schedule = p
+            .comments
+            .iter()
+            .flat_map(|c| c.get_tags())
+            .find(|t| t.get_name().eq_ignore_ascii_case(TAG));
+        let depreciation = match schedule {
+            Some(tag) => Depreciation::try_from(tag.value.as_deref().unwrap_or(""))?,
+            None => continue,
+        };
+        let (amount, currency) = match (&p.money_amount, &p.money_currency) {
+            (Some(amount), Some(currency)) => (amount, currency),
+            _ => return Err(DepreciationError::MissingAmount(p.account.clone())),
+        };
+        // The amount of the posting is already in the precision of the commodity
+        let decimals = commodities
+            .get(currency)
+            .map_or(2, |c| c.rounding_precision(0));
+        let purchase = p.date.unwrap();
+        let description = format!("Depreciation of {}", p.account);
+        for (i, monthly) in depreciation
+            .amounts(amount, decimals)
+            .into_iter()
+            .enumerate()
+        {
+            let date = purchase.checked_add_months(Months::new(i as u32 + 1));
+            let mut transaction = Transaction::<RawPosting>::new(TransactionType::Real);
+            transaction.date = date;
+            transaction.description = description.clone();
+            transaction.payee = Some(description.clone());
+            let posting = |account: &str, amount: BigRational| {
+                let mut posting = RawPosting::new();
+                posting.account = account.to_string();
+                posting.date = date;
+                posting.money_amount = Some(amount);
+                posting.money_currency = Some(currency.clone());
+                posting.payee = Some(description.clone());
+                posting
+            };
+            transaction.postings = RefCell::new(vec![
+                posting(&depreciation.account, monthly.clone()),
+                posting(&p.account, -monthly),
+            ]);
+            transactions.push(transaction);
+        }
+    }
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedules() {
+        let depreciation = Depreciation::try_from("straight-line 3 years").unwrap();
+        assert_eq!(depreciation.months, 36);
+        assert_eq!(depreciation.account, DEFAULT_ACCOUNT);
+        let depreciation =
+            Depreciation::try_from("Straight-Line 2 months to Expenses:Office equipment").unwrap();
+        assert_eq!(depreciation.account, "Expenses:Office equipment");
+        assert!(Depreciation::try_from("declining-balance 36 months").is_err());
+        assert!(Depreciation::try_from("straight-line 0 months").is_err());
+    }
+
+    #[test]
+    fn amounts_add_up() {
+        let depreciation = Depreciation::try_from("straight-line 3 months").unwrap();
+        let cents = |x: i64| BigRational::new(BigInt::from(x), BigInt::from(100));
+        assert_eq!(
+            depreciation.amounts(&cents(10_000), 2),
+            vec![cents(3333), cents(3334), cents(3333)]
+        );
+    }
+}
@@ -99,6 +99,53 @@ impl Money {
             false => self.clone(),
         }
     }
+    /// The amount as a journal reads it back, whatever the display format of the commodity
+    ///
+    /// The number goes first, with as many decimals as the commodity is written or displayed
+    /// with, and the commodity is quoted when it is not a plain word.
+    pub fn to_journal_string(&self) -> String {
+        match self {
+            Money::Zero => "0".to_string(),
+            Money::Money { amount, currency } => {
+                let format = currency.display_format.borrow();
+                let decimals = format.precision.max(format.max_decimals.unwrap_or(0));
+                let name = currency.get_name();
+                let quoted = name.is_empty()
+                    | name.chars().any(|c| {
+                        c.is_ascii_digit()
+                            | c.is_whitespace()
+                            | "\"'+*/\\|%<>:?()[];=&{}-@".contains(c)
+                    });
+                match quoted {
+                    true => format!("{} \"{}\"", plain_decimal(amount, decimals), name),
+                    false => format!("{} {}", plain_decimal(amount, decimals), name),
+                }
+            }
+        }
+    }
+}
+
+/// A number rounded to `decimals`, with a dot as the decimal mark and no thousands separators
+pub fn plain_decimal(amount: &BigRational, decimals: usize) -> String {
+    let scale = BigInt::from(10).pow(decimals as u32);
+    let units = (amount * BigRational::from_integer(scale.clone()))
+        .round()
+        .to_integer();
+    let sign = match units.is_negative() {
+        true => "-",
+        false => "",
+    };
+    let units = units.abs();
+    match decimals {
+        0 => format!("{}{}", sign, units),
+        _ => format!(
+            "{}{}.{:0>width$}",
+            sign,
+            &units / &scale,
+            (&units % &scale).to_string(),
+            width = decimals
+        ),
+    }
 }
 impl Eq for Money {}
 
@@ -362,7 +409,7 @@ mod tests {
 
     use crate::models::{Currency, CurrencyDisplayFormat};
 
-    use super::Money;
+    use super::{plain_decimal, Money};
 
     #[test]
     fn rounding() {
@@ -379,4 +426,27 @@ mod tests {
         eur.set_format(&no_decimal_format);
         assert_eq!(format!("{}", &m1), "-18 EUR");
     }
+
+    #[test]
+    fn journal_strings() {
+        let eur = Rc::new(Currency::from("EUR"));
+        eur.set_format(&CurrencyDisplayFormat::from("-1.234,56 EUR"));
+        let m1 = Money::from((eur.clone(), BigRational::from_float(-1234.5).unwrap()));
+        assert_eq!(format!("{}", &m1), "-1.234,50 EUR");
+        assert_eq!(m1.to_journal_string(), "-1234.50 EUR");
+        let shares = Rc::new(Currency::from("VANGUARD 500"));
+        shares.update_precision(2);
+        let m2 = Money::from((shares, BigRational::from_float(0.25).unwrap()));
+        assert_eq!(m2.to_journal_string(), "0.25 \"VANGUARD 500\"");
+
+        let big: BigRational = "12345678901234568/100".parse().unwrap();
+        assert_eq!(plain_decimal(&big, 2), "123456789012345.68");
+        assert_eq!(plain_decimal(&-big, 0), "-123456789012346");
+        let small: BigRational = "-1/200".parse().unwrap();
+        assert_eq!(plain_decimal(&small, 2), "-0.01");
+        assert_eq!(
+            plain_decimal(&(small / BigRational::from_float(10.0).unwrap()), 2),
+            "0.00"
+        );
+    }
 }
@@ -0,0 +1,167 @@
+//! The period of a periodic transaction, like `~ monthly from 2021-01-01`
+//!
+//! The supported periods are `daily`, `weekly`, `biweekly`, `monthly`, `bimonthly`, `quarterly`,
+//! `yearly` (or `annually`) and `every N days`, `every N weeks`, `every N months`,
+//! `every N quarters` or `every N years`, optionally followed by `from DATE` and `to DATE`.
+use std::convert::TryFrom;
+
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::error::InvalidPeriodError;
+use crate::parser::utils::parse_str_as_date;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interval {
+    Days(u32),
+    Months(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Periodicity {
+    interval: Interval,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+impl TryFrom<&str> for Periodicity {
+    type Error = InvalidPeriodError;
+    fn try_from(period: &str) -> Result<Self, Self::Error> {
+        lazy_static! {
+            static ref RE_PERIOD: Regex = Regex::new(concat!(
+                r"(?i)^\s*(?:every\s+(?P<n>\d+)\s+(?P<unit>day|week|month|quarter|year)s?",
+                r"|every\s+(?P<single>day|week|month|quarter|year)",
+                r"|(?P<name>daily|weekly|biweekly|monthly|bimonthly|quarterly|yearly|annually))",
+                r"(?:\s+from\s+(?P<from>\d{4}[/-]\d\d?(?:[/-]\d\d?)?))?",
+                r"(?:\s+to\s+(?P<to>\d{4}[/-]\d\d?(?:[/-]\d\d?)?))?\s*$"
+            ))
+            .unwrap();
+        }
+        let error = || InvalidPeriodError(period.to_string());
+        let captures = RE_PERIOD.captures(period).ok_or_else(error)?;
+        let (n, unit) = match (
+            captures.name("n"),
+            captures.name("unit"),
+            captures.name("single"),
+            captures.name("name"),
+        ) {
+            (Some(n), Some(unit), _, _) => (n.as_str().parse().map_err(|_| error())?, unit),
+            (_, _, Some(unit), _) => (1, unit),
+            (_, _, _, Some(name)) => match name.as_str().to_lowercase().starts_with("bi") {
+                true => (2, name),
+                false => (1, name),
+            },
+            _ => return Err(error()),
+        };
+        if n == 0 {
+            return Err(error());
+        }
+        let unit = unit.as_str().to_lowercase().replace("bi", "");
+        let interval = if unit.starts_with("da") {
+            Interval::Days(n)
+        } else if unit.starts_with("week") {
+            Interval::Days(7 * n)
+        } else if unit.starts_with("month") {
+            Interval::Months(n)
+        } else if unit.starts_with("quarter") {
+            Interval::Months(3 * n)
+        } else {
+            Interval::Months(12 * n)
+        };
+        let date = |name| captures.name(name).map(|x| parse_period_date(x.as_str()));
+        Ok(Periodicity {
+            interval,
+            from: date("from"),
+            to: date("to"),
+        })
+    }
+}
+
+/// Dates can be written without the day, `2021-03` is the first of March
+fn parse_period_date(date: &str) -> NaiveDate {
+    match date.chars().filter(|c| (*c == '-') | (*c == '/')).count() {
+        1 => parse_str_as_date(&format!("{}-01", date.replace('/', "-"))),
+        _ => parse_str_as_date(&date.replace('/', "-")),
+    }
+}
+
+impl Periodicity {
+    /// The dates of the transaction from `begin` (inclusive) to `end` (exclusive)
+    ///
+    /// Without a `from` date, the dates are aligned to the beginning of the day, week, month or year.
+    pub fn dates(&self, begin: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+        let end = match self.to {
+            Some(to) => to.min(end),
+            None => end,
+        };
+        let mut date = match self.from {
+            Some(from) => from,
+            None => match self.interval {
+                Interval::Days(n) if n % 7 == 0 => {
+                    Some(begin - Duration::days(begin.weekday().num_days_from_monday() as i64))
+                }
+                Interval::Days(_) => Some(begin),
+                Interval::Months(n) if n % 12 == 0 => NaiveDate::from_ymd_opt(begin.year(), 1, 1),
+                Interval::Months(_) => NaiveDate::from_ymd_opt(begin.year(), begin.month(), 1),
+            }
+            .unwrap(),
+        };
+        let mut dates = vec![];
+        let mut i = 0;
+        while date < end {
+            if date >= begin {
+                dates.push(date);
+            }
+            i += 1;
+            // Counting from the first date keeps the 31st as the 31st when it exists
+            date = match (self.interval, self.from) {
+                (Interval::Months(n), Some(from)) => {
+                    from.checked_add_months(Months::new(n * i)).unwrap()
+                }
+                (Interval::Months(n), None) => date.checked_add_months(Months::new(n)).unwrap(),
+                (Interval::Days(n), Some(from)) => from + Duration::days(n as i64 * i as i64),
+                (Interval::Days(n), None) => date + Duration::days(n as i64),
+            };
+        }
+        dates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn monthly() {
+        let period = Periodicity::try_from("monthly").unwrap();
+        assert_eq!(
+            period.dates(date(2021, 1, 15), date(2021, 4, 1)),
+            vec![date(2021, 2, 1), date(2021, 3, 1)]
+        );
+        let period = Periodicity::try_from("Monthly from 2021/01/31 to 2021-04").unwrap();
+        assert_eq!(
+            period.dates(date(2021, 1, 1), date(2022, 1, 1)),
+            vec![date(2021, 1, 31), date(2021, 2, 28), date(2021, 3, 31)]
+        );
+    }
+
+    #[test]
+    fn every_n() {
+        let period = Periodicity::try_from("every 2 weeks from 2021-01-04").unwrap();
+        assert_eq!(
+            period.dates(date(2021, 1, 10), date(2021, 2, 1)),
+            vec![date(2021, 1, 18)]
+        );
+        assert_eq!(
+            Periodicity::try_from("every 3 months").unwrap(),
+            Periodicity::try_from("quarterly").unwrap()
+        );
+        assert!(Periodicity::try_from("every now and then").is_err());
+        assert!(Periodicity::try_from("every 0 days").is_err());
+    }
+}
@@ -27,6 +27,9 @@ impl Price {
     pub fn get_price(&self) -> Money {
         self.price.clone()
     }
+    pub fn get_commodity(&self) -> Rc<Currency> {
+        self.commodity.clone()
+    }
 }
 
 impl Display for Price {
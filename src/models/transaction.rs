@@ -1,12 +1,13 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::ops::Deref;
+use std::path::PathBuf;
 use std::rc::{Rc, Weak};
 
 use chrono::NaiveDate;
 use num::rational::BigRational;
 
-use crate::error::{BalanceError, LedgerError};
+use crate::error::{BalanceError, LedgerError, UnknownAccountTypeError};
 use crate::models::balance::Balance;
 use crate::models::{Account, Comment, HasName, Money, Payee};
 use crate::List;
@@ -31,6 +32,8 @@ pub struct Transaction<PostingType> {
     pub comments: Vec<Comment>,
     pub transaction_type: TransactionType,
     pub tags: Vec<Tag>,
+    /// The period of a periodic transaction, like `monthly from 2021-01-01`
+    pub period: Option<String>,
     filter_query: Option<String>,
 }
 
@@ -41,12 +44,27 @@ pub struct Posting {
     pub amount: Option<Money>,
     pub balance: Option<Money>,
     pub cost: Option<Cost>,
+    /// The price per unit of the lot, which the cost of a sale does not replace
+    pub lot_price: Option<Money>,
+    /// A fixated lot, `{=P}`, is never revalued
+    pub lot_fixated: bool,
     pub kind: PostingType,
     pub comments: Vec<Comment>,
     pub tags: RefCell<Vec<Tag>>,
     pub payee: Option<Rc<Payee>>,
     pub transaction: RefCell<Weak<Transaction<Posting>>>,
     pub origin: PostingOrigin,
+    /// The status of the posting, which is the one of the transaction unless it has its own
+    pub cleared: Cleared,
+    /// Where the posting was written, automated postings have none
+    pub source: Option<SourceLocation>,
+}
+
+/// The file and line of a posting in the journal
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub line: usize,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -57,7 +75,7 @@ pub enum PostingOrigin {
 }
 
 impl<T> Transaction<T> {
-    pub fn get_filter_query(&mut self) -> String {
+    pub fn get_filter_query(&mut self) -> Result<String, UnknownAccountTypeError> {
         match self.filter_query.clone() {
             None => {
                 let mut parts: Vec<String> = vec![];
@@ -80,11 +98,11 @@ impl<T> Transaction<T> {
                 }
                 parts.push(current);
                 //self.description.split(' ').map(|x| x.to_string()).collect();
-                let res = preprocess_query(&parts, &false);
+                let res = preprocess_query(&parts, &false)?;
                 self.filter_query = Some(res.clone());
-                res
+                Ok(res)
             }
-            Some(x) => x,
+            Some(x) => Ok(x),
         }
     }
     pub fn get_payee(&self, payees: &List<Payee>) -> Option<Rc<Payee>> {
@@ -119,6 +137,9 @@ pub enum TransactionType {
 pub enum Cleared {
     Unknown,
     NotCleared,
+    /// Marked with `!`
+    Pending,
+    /// Marked with `*`
     Cleared,
 }
 
@@ -143,17 +164,33 @@ impl Posting {
             date,
             balance: None,
             cost: None,
+            lot_price: None,
+            lot_fixated: false,
             kind,
             comments: vec![],
             tags: RefCell::new(vec![]),
             payee: Some(Rc::new(payee.clone())),
             transaction: RefCell::new(Default::default()),
             origin,
+            cleared: Cleared::Unknown,
+            source: None,
         }
     }
     pub fn set_amount(&mut self, money: Money) {
         self.amount = Some(money)
     }
+    /// The amount to convert to another currency, a fixated lot is worth its lot price whatever
+    /// the market price of the commodity
+    pub fn exchange_amount(&self) -> Money {
+        let amount = self.amount.clone().unwrap();
+        match &self.lot_price {
+            Some(price) if self.lot_fixated => match price.get_commodity() {
+                Some(currency) => Money::from((currency, price.get_amount() * amount.get_amount())),
+                None => amount,
+            },
+            _ => amount,
+        }
+    }
     pub fn has_tag(&self, regex: Regex) -> bool {
         for t in self.tags.borrow().iter() {
             if regex.is_match(t.get_name()) {
@@ -178,6 +215,15 @@ impl Posting {
         }
         None
     }
+    /// The account named after the value of the tag, for reports that pivot on it
+    ///
+    /// Postings without a value for the tag go to the `(none)` account
+    pub fn get_pivot_account(&self, tag: &str) -> Rc<Account> {
+        match self.get_exact_tag(tag.to_string()) {
+            Some(value) if !value.trim().is_empty() => Rc::new(Account::from(value.trim())),
+            _ => Rc::new(Account::from("(none)")),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -200,6 +246,7 @@ impl<PostingType> Transaction<PostingType> {
             comments: vec![],
             transaction_type: t_type,
             tags: vec![],
+            period: None,
             filter_query: None,
         }
     }
@@ -288,6 +335,9 @@ impl Transaction<Posting> {
         // 1. Iterate over postings
         let mut fill_account = Rc::new(Account::from("this will never be used"));
         let mut fill_payee = None;
+        let mut fill_cleared = self.cleared;
+        let mut fill_source = None;
+        let mut fill_origin = PostingOrigin::FromTransaction;
         let mut fill_date: NaiveDate = NaiveDate::from_ymd(1900, 1, 1); // it will be overwritten
         let mut postings: Vec<Posting> = Vec::new();
 
@@ -299,7 +349,9 @@ impl Transaction<Posting> {
             if let Some(money) = &p.amount {
                 let expected_balance = balances.get(p.account.deref()).unwrap().clone()  // What we had 
                     + Balance::from(money.clone()); // What we add
-                if !skip_balance_check {
+
+                // Forecast postings do not check balance assertions
+                if !skip_balance_check & (p.origin != PostingOrigin::Periodic) {
                     if let Some(balance) = &p.balance {
                         if Balance::from(balance.clone()) != expected_balance {
                             eprintln!("Found:       {}", balance);
@@ -357,12 +409,16 @@ impl Transaction<Posting> {
                     date: p.date,
                     balance: p.balance.clone(),
                     cost: p.cost.clone(),
+                    lot_price: p.lot_price.clone(),
+                    lot_fixated: p.lot_fixated,
                     kind: PostingType::Real,
                     comments: p.comments.clone(),
                     tags: p.tags.clone(),
                     payee: p.payee.clone(),
                     transaction: p.transaction.clone(),
-                    origin: PostingOrigin::FromTransaction,
+                    origin: p.origin,
+                    cleared: p.cleared,
+                    source: p.source.clone(),
                 });
             } else if p.balance.is_some() & !skip_balance_check {
                 // There is a balance
@@ -381,18 +437,25 @@ impl Transaction<Posting> {
                     amount: Some(money),
                     balance: p.balance.clone(),
                     cost: p.cost.clone(),
+                    lot_price: p.lot_price.clone(),
+                    lot_fixated: p.lot_fixated,
                     kind: PostingType::Real,
                     comments: p.comments.clone(),
                     tags: p.tags.clone(),
                     payee: p.payee.clone(),
                     transaction: p.transaction.clone(),
-                    origin: PostingOrigin::FromTransaction,
+                    origin: p.origin,
+                    cleared: p.cleared,
+                    source: p.source.clone(),
                 });
             } else {
                 // We do nothing, but this is the account for the empty post
                 fill_account = p.account.clone();
                 fill_payee = p.payee.clone();
                 fill_date = p.date;
+                fill_cleared = p.cleared;
+                fill_source = p.source.clone();
+                fill_origin = p.origin;
             }
         }
 
@@ -437,13 +500,17 @@ impl Transaction<Posting> {
                     amount: Some(money.clone()),
                     balance: None,
                     cost: None,
+                    lot_price: None,
+                    lot_fixated: false,
                     kind: PostingType::Real,
                     comments: self.comments.clone(),
                     tags: RefCell::new(self.tags.clone()),
                     payee: fill_payee.clone(),
                     date: fill_date,
                     transaction: self.postings.borrow()[0].transaction.clone(),
-                    origin: PostingOrigin::FromTransaction,
+                    origin: fill_origin,
+                    cleared: fill_cleared,
+                    source: fill_source.clone(),
                 });
             }
             // self.postings = RefCell::new(postings);
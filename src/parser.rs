@@ -9,12 +9,14 @@
 use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::fs::read_to_string;
+use std::io::Read;
 use std::path::PathBuf;
 
 use crate::error::MissingFileError;
 use crate::models::{Account, Comment, Currency, HasName, Payee, Transaction};
-use crate::parser::utils::count_decimals;
+use crate::parser::utils::{count_decimals, expression_currencies, parse_string, valid_number};
 use crate::{models, CommonOpts, List};
+use pest::iterators::Pair;
 use pest::Parser;
 
 mod include;
@@ -23,6 +25,8 @@ pub mod tokenizers;
 pub(crate) mod utils;
 pub mod value_expr;
 
+use chrono::Local;
+use tokenizers::timeclock::{ClockEntry, TIMECLOCK_COMMODITY};
 use tokenizers::transaction;
 
 #[derive(Parser)]
@@ -65,8 +69,8 @@ impl ParsedLedger {
         self.commodities.append(&other.commodities);
         self.transactions.append(&mut other.transactions);
         self.comments.append(&mut other.comments);
-        self.transactions.append(&mut other.transactions);
         self.prices.append(&mut other.prices);
+        self.tags.append(&mut other.tags);
         self.files.append(&mut other.files);
     }
 
@@ -90,19 +94,35 @@ pub struct Tokenizer<'a> {
     file: Option<&'a PathBuf>,
     content: String,
     seen_files: HashSet<&'a PathBuf>,
+    /// The decimal mark set with the ```decimal-mark``` directive, without one the amounts have
+    /// the decimal mark of their commodity format or a point
+    decimal_mark: Option<char>,
+    /// The offset where every line of the content starts
+    line_starts: Vec<usize>,
 }
 
 impl<'a> TryFrom<&'a PathBuf> for Tokenizer<'a> {
     type Error = Box<dyn std::error::Error>;
+    /// Reads the file, the special path ```-``` reads from the standard input
     fn try_from(file: &'a PathBuf) -> Result<Self, Self::Error> {
-        match read_to_string(file) {
+        let content = if file.as_os_str() == "-" {
+            let mut content = String::new();
+            std::io::stdin()
+                .read_to_string(&mut content)
+                .map(|_| content)
+        } else {
+            read_to_string(file)
+        };
+        match content {
             Ok(content) => {
                 let mut seen_files: HashSet<&PathBuf> = HashSet::new();
                 seen_files.insert(file);
                 Ok(Tokenizer {
                     file: Some(file),
+                    line_starts: line_starts(&content),
                     content,
                     seen_files,
+                    decimal_mark: None,
                 })
             }
             Err(err) => match err.kind() {
@@ -119,9 +139,76 @@ impl<'a> From<String> for Tokenizer<'a> {
     fn from(content: String) -> Self {
         Tokenizer {
             file: None,
+            line_starts: line_starts(&content),
             content,
             seen_files: HashSet::new(),
+            decimal_mark: None,
+        }
+    }
+}
+
+/// The decimal mark of the amounts of a commodity: the one of the journal if it has one, else
+/// the one of the declared format of the commodity (as in ```commodity 1.000,00 EUR```), else a
+/// point
+pub(crate) fn decimal_mark_of(
+    commodities: &List<Currency>,
+    currency: &str,
+    decimal_mark: Option<char>,
+) -> char {
+    decimal_mark
+        .or_else(|| commodities.get(currency).ok()?.get_decimal_mark())
+        .unwrap_or('.')
+}
+
+/// The first number of a journal element that is not valid with its decimal mark, and the mark
+///
+/// The amounts of the declared commodities can have their own decimal mark, except in value
+/// expressions, where there are no commodities.
+fn invalid_number<'i>(
+    pair: Pair<'i, Rule>,
+    decimal_mark: Option<char>,
+    commodities: Option<&List<Currency>>,
+) -> Option<(Pair<'i, Rule>, char)> {
+    match pair.as_rule() {
+        Rule::number if !valid_number(pair.as_str(), decimal_mark.unwrap_or('.')) => {
+            Some((pair, decimal_mark.unwrap_or('.')))
         }
+        // Value expressions are evaluated with a decimal point
+        Rule::value_expr => pair
+            .into_inner()
+            .find_map(|x| invalid_number(x, Some('.'), None)),
+        // The number of a price is in the last commodity
+        Rule::money | Rule::price if commodities.is_some() => {
+            let mark = pair
+                .clone()
+                .into_inner()
+                .filter(|x| matches!(x.as_rule(), Rule::currency | Rule::commodity_in_directive))
+                .last()
+                .map(|x| decimal_mark_of(commodities.unwrap(), &parse_string(x), decimal_mark))
+                .or(decimal_mark);
+            pair.into_inner()
+                .find_map(|x| invalid_number(x, mark, commodities))
+        }
+        _ => pair
+            .into_inner()
+            .find_map(|x| invalid_number(x, decimal_mark, commodities)),
+    }
+}
+
+/// The offsets where the lines of a text start
+fn line_starts(content: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect()
+}
+
+impl<'a> Tokenizer<'a> {
+    /// The line number, starting at one, of an offset of the content
+    ///
+    /// pest's `line_col` scans the content from the start, which is too slow to do for every
+    /// posting of a large journal.
+    pub(crate) fn line_of(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|x| *x <= offset)
     }
 }
 
@@ -143,10 +230,28 @@ impl<'a> Tokenizer<'a> {
         if let Some(file) = self.file {
             ledger.files.push(file.clone());
         }
+        // Timeclock entries that have been checked in but not out yet
+        let mut clocked_in: Vec<ClockEntry> = vec![];
         match GrammarParser::parse(Rule::journal, self.content.as_str()) {
             Ok(mut parsed) => {
                 let elements = parsed.next().unwrap().into_inner();
                 for element in elements {
+                    if let Some((number, mark)) = invalid_number(
+                        element.clone(),
+                        self.decimal_mark,
+                        Some(&ledger.commodities),
+                    ) {
+                        if let Some(file) = &self.file {
+                            eprintln!("Can't parse {:?}", file);
+                        }
+                        eprintln!(
+                            "Error found in line {}: {} is ambiguous with {} as the decimal mark",
+                            self.line_of(number.as_span().start()),
+                            number.as_str(),
+                            mark
+                        );
+                        return ParsedLedger::new();
+                    }
                     match element.as_rule() {
                         Rule::directive => {
                             let inner = element.into_inner().next().unwrap();
@@ -158,7 +263,9 @@ impl<'a> Tokenizer<'a> {
                                     ledger.append(&mut new_ledger);
                                 }
                                 Rule::price => {
-                                    ledger.prices.push(self.parse_price(inner));
+                                    ledger
+                                        .prices
+                                        .push(self.parse_price(inner, &ledger.commodities));
                                 }
                                 Rule::tag_dir => {
                                     ledger.tags.push(self.parse_tag(inner));
@@ -179,18 +286,35 @@ impl<'a> Tokenizer<'a> {
                                 Rule::payee_dir => {
                                     ledger.payees.insert(self.parse_payee(inner));
                                 }
+                                Rule::decimal_mark => {
+                                    let mark = inner.into_inner().next().unwrap().as_str();
+                                    self.decimal_mark = mark.chars().next();
+                                }
                                 _ => {}
                             }
                         }
-                        Rule::transaction | Rule::automated_transaction => {
-                            let transaction = self.parse_transaction(element);
+                        Rule::transaction
+                        | Rule::automated_transaction
+                        | Rule::periodic_transaction => {
+                            let transaction = self.parse_transaction(element, &ledger.commodities);
                             for posting in transaction.postings.borrow().iter() {
-                                let currencies = &[
-                                    (&posting.money_currency, &posting.money_format),
-                                    (&posting.cost_currency, &posting.cost_format),
-                                    (&posting.balance_currency, &posting.balance_format),
+                                let mut currencies = vec![
+                                    (posting.money_currency.clone(), posting.money_format.clone()),
+                                    (posting.cost_currency.clone(), posting.cost_format.clone()),
+                                    (posting.lot_currency.clone(), posting.lot_format.clone()),
+                                    (
+                                        posting.balance_currency.clone(),
+                                        posting.balance_format.clone(),
+                                    ),
                                 ];
-                                for (currency, format) in currencies {
+                                for expression in [&posting.amount_expr, &posting.cost_expr]
+                                    .iter()
+                                    .copied()
+                                    .flatten()
+                                {
+                                    currencies.append(&mut expression_currencies(expression));
+                                }
+                                for (currency, format) in currencies.iter() {
                                     if let Some(c) = currency {
                                         match ledger.commodities.get(c) {
                                             Err(_) => {
@@ -205,9 +329,16 @@ impl<'a> Tokenizer<'a> {
                                                 }
 
                                                 let commodity = Currency::from(c.as_str());
+                                                if self.decimal_mark == Some(',') {
+                                                    let mut display_format =
+                                                        commodity.display_format.borrow_mut();
+                                                    display_format.set_decimal_separator(',');
+                                                    display_format.set_thousands_separator('.');
+                                                }
                                                 if let Some(format_string) = format {
                                                     commodity.update_precision(count_decimals(
                                                         format_string.as_str(),
+                                                        self.decimal_mark.unwrap_or('.'),
                                                     ));
                                                 }
                                                 ledger.commodities.insert(commodity);
@@ -216,6 +347,9 @@ impl<'a> Tokenizer<'a> {
                                                 if let Some(format_string) = format {
                                                     c.update_precision(count_decimals(
                                                         format_string.as_str(),
+                                                        self.decimal_mark
+                                                            .or_else(|| c.get_decimal_mark())
+                                                            .unwrap_or('.'),
                                                     ));
                                                 }
                                             }
@@ -225,11 +359,51 @@ impl<'a> Tokenizer<'a> {
                             }
                             ledger.transactions.push(transaction);
                         }
+                        Rule::timeclock => {
+                            let entry = self.parse_timeclock(element);
+                            if entry.clock_in {
+                                if ledger.commodities.get(TIMECLOCK_COMMODITY).is_err() {
+                                    let hours = Currency::from(TIMECLOCK_COMMODITY);
+                                    hours.update_precision(2);
+                                    ledger.commodities.insert(hours);
+                                }
+                                clocked_in.push(entry);
+                                continue;
+                            }
+                            // Check out of the same account or of the only open entry
+                            let index = match &entry.account {
+                                Some(account) => clocked_in
+                                    .iter()
+                                    .position(|x| x.account.as_ref() == Some(account)),
+                                None if clocked_in.len() == 1 => Some(0),
+                                None => None,
+                            };
+                            match index {
+                                Some(i) => ledger
+                                    .transactions
+                                    .push(clocked_in.remove(i).to_transaction(&entry)),
+                                None => eprintln!(
+                                    "Warning: check out at {} without a matching check in.",
+                                    entry.datetime
+                                ),
+                            }
+                        }
                         _x => {
                             // eprintln!("{:?}", x);
                         }
                     }
                 }
+                // Entries that are still open are checked out now
+                let now = ClockEntry {
+                    clock_in: false,
+                    datetime: Local::now().naive_local(),
+                    account: None,
+                    payee: None,
+                    comments: vec![],
+                };
+                for entry in clocked_in.iter() {
+                    ledger.transactions.push(entry.to_transaction(&now));
+                }
             }
             Err(e) => {
                 if let Some(file) = &self.file {
@@ -263,4 +437,14 @@ mod tests {
         let items = tokenizer.tokenize(&CommonOpts::from_iter(["", "-f", ""].iter()));
         assert_eq!(items.len(), 0, "Should be empty")
     }
+
+    #[test]
+    fn line_numbers() {
+        let tokenizer = Tokenizer::from("a\nbc\n\nd".to_string());
+        assert_eq!(tokenizer.line_of(0), 1);
+        assert_eq!(tokenizer.line_of(1), 1);
+        assert_eq!(tokenizer.line_of(2), 2);
+        assert_eq!(tokenizer.line_of(5), 3);
+        assert_eq!(tokenizer.line_of(6), 4);
+    }
 }
@@ -48,6 +48,7 @@ impl<'a> Tokenizer<'a> {
             for p in self.seen_files.iter() {
                 inner_tokenizer.seen_files.insert(*p);
             }
+            inner_tokenizer.decimal_mark = self.decimal_mark;
             let mut new_items: ParsedLedger =
                 inner_tokenizer.tokenize_with_currencies(options, Some(commodities));
             items.append(&mut new_items);
@@ -1,7 +1,9 @@
 use super::super::Rule;
+use crate::models::AccountType;
 use crate::parser::Tokenizer;
 use crate::{models::Account, parser::utils::parse_string};
 
+use lazy_static::lazy_static;
 use pest::iterators::Pair;
 use regex::Regex;
 
@@ -36,6 +38,24 @@ impl<'a> Tokenizer<'a> {
                     }
                 }
                 Rule::flag => account.default = true,
+                Rule::comment => {
+                    // hledger style account types, like ; type: A
+                    lazy_static! {
+                        static ref RE_TYPE: Regex =
+                            Regex::new(r"(?i)(^|[\s,])type:\s*([a-z]+)").unwrap();
+                    }
+                    let comment = parse_string(part.into_inner().next().unwrap());
+                    if let Some(captures) = RE_TYPE.captures(comment.as_str()) {
+                        let account_type = captures.get(2).unwrap().as_str();
+                        match AccountType::parse(account_type) {
+                            Some(x) => account.account_type = Some(x),
+                            None => eprintln!(
+                                "Warning: unknown type {} for account {}",
+                                account_type, account
+                            ),
+                        }
+                    }
+                }
                 _x => {}
             }
         }
@@ -108,4 +128,23 @@ mod tests {
         assert!(!account.check.is_empty(), "It has a check");
         assert!(!account.assert.is_empty(), "It has an assert");
     }
+
+    #[test]
+    fn account_types() {
+        let mut tokenizer = Tokenizer::from(
+            "account Assets:Bank  ; type: C
+account Assets:Broker  ; some comment, type:Asset
+account Debts  ; type: L
+account Assets:House
+"
+            .to_string(),
+        );
+        let options = CommonOpts::from_iter(["", "-f", ""].iter());
+        let items = tokenizer.tokenize(&options);
+        let account_type = |name| items.accounts.get(name).unwrap().get_account_type();
+        assert_eq!(account_type("Assets:Bank"), Some(AccountType::Cash));
+        assert_eq!(account_type("Assets:Broker"), Some(AccountType::Asset));
+        assert_eq!(account_type("Debts"), Some(AccountType::Liability));
+        assert_eq!(account_type("Assets:House"), None);
+    }
 }
@@ -3,16 +3,29 @@ use std::collections::HashSet;
 
 use crate::models::{Comment, Currency, CurrencyDisplayFormat};
 use crate::parser::utils::parse_string;
-use crate::parser::Tokenizer;
+use crate::parser::{GrammarParser, Tokenizer};
 
 use pest::iterators::Pair;
+use pest::Parser;
 
 impl<'a> Tokenizer<'a> {
     pub(crate) fn parse_commodity(&self, element: Pair<Rule>) -> Currency {
         let mut parsed = element.into_inner();
-        let name = parse_string(parsed.next().unwrap());
-        let mut note: Option<String> = None;
+        let spec = parsed.next().unwrap();
+        let quoted = spec.clone().into_inner().next().is_some();
+        let mut name = parse_string(spec);
         let mut format: Option<String> = None;
+        let mut decimal_mark = None;
+        // hledger style one line declaration, like commodity 1.000,00 EUR, which also sets the
+        // decimal mark of the amounts
+        if !quoted {
+            if let Some(symbol) = format_symbol(name.trim()) {
+                decimal_mark = format_decimal_mark(name.trim());
+                format = Some(name.trim().to_string());
+                name = symbol;
+            }
+        }
+        let mut note: Option<String> = None;
         let mut comments: Vec<Comment> = vec![];
         let mut default = false;
         let mut aliases = HashSet::new();
@@ -48,6 +61,9 @@ impl<'a> Tokenizer<'a> {
         if let Some(n) = note {
             currency.set_note(n);
         }
+        if let Some(mark) = decimal_mark {
+            currency.set_decimal_mark(mark);
+        }
         if let Some(f) = format {
             currency.format = Some(f.clone());
             currency.set_format(&CurrencyDisplayFormat::from(f.as_str()));
@@ -56,3 +72,37 @@ impl<'a> Tokenizer<'a> {
         currency
     }
 }
+
+/// The last separator of the number of a format, like the comma of ```1.000,00 EUR```
+fn format_decimal_mark(format: &str) -> Option<char> {
+    let start = format.find(|c: char| c.is_ascii_digit())?;
+    let end = format.rfind(|c: char| c.is_ascii_digit())?;
+    format[start..end]
+        .chars()
+        .rev()
+        .find(|c| (*c == '.') | (*c == ','))
+}
+
+/// Returns the commodity symbol if the commodity declaration is a format, like ```1.000,00 EUR``` or ```$1,000.00```
+fn format_symbol(spec: &str) -> Option<String> {
+    if !spec.contains(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    // Commodity names can have numbers, only a number separated from the name or preceded by a symbol is a format
+    let first = spec.chars().next().unwrap();
+    if first.is_alphanumeric() & !first.is_ascii_digit() & !spec.contains(char::is_whitespace) {
+        return None;
+    }
+    let parsed = GrammarParser::parse(Rule::currency_format, spec).ok()?;
+    if parsed.as_str() != spec {
+        return None;
+    }
+    let symbol = parsed
+        .flatten()
+        .find(|x| x.as_rule() == Rule::currency_string)?;
+    let symbol = parse_string(symbol);
+    match symbol.trim() {
+        "" => None,
+        x => Some(x.trim_matches('"').to_string()),
+    }
+}
@@ -3,4 +3,5 @@ pub(crate) mod commodity;
 pub(crate) mod payee;
 pub(crate) mod price;
 pub(crate) mod tag;
+pub(crate) mod timeclock;
 pub(crate) mod transaction;
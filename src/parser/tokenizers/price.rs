@@ -1,12 +1,17 @@
 use super::super::Rule;
-use crate::models::ParsedPrice;
-use crate::parser::utils::{parse_date, parse_rational, parse_string};
-use crate::parser::Tokenizer;
+use crate::models::{Currency, ParsedPrice};
+use crate::parser::utils::{parse_date, parse_decimal, parse_string};
+use crate::parser::{decimal_mark_of, Tokenizer};
+use crate::List;
 
 use pest::iterators::Pair;
 
 impl<'a> Tokenizer<'a> {
-    pub(crate) fn parse_price(&self, element: Pair<Rule>) -> ParsedPrice {
+    pub(crate) fn parse_price(
+        &self,
+        element: Pair<Rule>,
+        commodities: &List<Currency>,
+    ) -> ParsedPrice {
         let mut parsed = element.into_inner();
         let date = parse_date(parsed.next().unwrap());
         let commodity = {
@@ -16,8 +21,12 @@ impl<'a> Tokenizer<'a> {
                 _ => parse_string(time_or_commodity),
             }
         };
-        let amount = parse_rational(parsed.next().unwrap());
+        let number = parsed.next().unwrap();
         let other_commodity = parse_string(parsed.next().unwrap());
+        let amount = parse_decimal(
+            number,
+            decimal_mark_of(commodities, &other_commodity, self.decimal_mark),
+        );
 
         ParsedPrice {
             date,
@@ -0,0 +1,131 @@
+use super::super::Rule;
+use super::transaction::RawPosting;
+use crate::models::{Comment, PostingType, Transaction, TransactionType};
+use crate::parser::utils::{parse_date, parse_string, parse_time};
+use crate::parser::Tokenizer;
+use chrono::NaiveDateTime;
+use num::{rational::BigRational, BigInt};
+use pest::iterators::Pair;
+
+/// The commodity in which the time spent is recorded
+pub(crate) const TIMECLOCK_COMMODITY: &str = "h";
+
+/// A timeclock entry, either checking in or checking out
+///
+/// ```ledger
+/// i 2021/03/01 09:00:00 Client:Project  Payee
+/// o 2021/03/01 12:30:00
+/// ```
+#[derive(Debug, Clone)]
+pub(crate) struct ClockEntry {
+    pub(crate) clock_in: bool,
+    pub(crate) datetime: NaiveDateTime,
+    pub(crate) account: Option<String>,
+    pub(crate) payee: Option<String>,
+    pub(crate) comments: Vec<Comment>,
+}
+
+impl<'a> Tokenizer<'a> {
+    /// Parses a timeclock entry
+    pub(crate) fn parse_timeclock(&self, element: Pair<Rule>) -> ClockEntry {
+        let inner = element.into_inner().next().unwrap();
+        let clock_in = inner.as_rule() == Rule::clock_in;
+        let mut parsed = inner.into_inner();
+        let date = parse_date(parsed.next().unwrap());
+        let time = parse_time(parsed.next().unwrap());
+        let mut entry = ClockEntry {
+            clock_in,
+            datetime: date.and_time(time),
+            account: None,
+            payee: None,
+            comments: vec![],
+        };
+        for part in parsed {
+            match part.as_rule() {
+                Rule::account => entry.account = Some(part.as_str().trim().to_string()),
+                Rule::payee => {
+                    let payee = parse_string(part).trim().to_string();
+                    if !payee.is_empty() {
+                        entry.payee = Some(payee);
+                    }
+                }
+                Rule::comment => entry.comments.push(Comment::from(parse_string(
+                    part.into_inner().next().unwrap(),
+                ))),
+                _x => {}
+            }
+        }
+        entry
+    }
+}
+
+impl ClockEntry {
+    /// Creates the transaction for the time elapsed between checking in and checking out
+    ///
+    /// The time is recorded in hours as an unbalanced virtual posting, so it never interferes
+    /// with the real postings of the journal
+    pub(crate) fn to_transaction(&self, clock_out: &ClockEntry) -> Transaction<RawPosting> {
+        let account = self.account.clone().unwrap();
+        let mut transaction = Transaction::<RawPosting>::new(TransactionType::Real);
+        transaction.date = Some(self.datetime.date());
+        transaction.description = match &self.payee {
+            Some(payee) => payee.clone(),
+            None => account.clone(),
+        };
+        transaction.payee = Some(transaction.description.clone());
+        transaction.comments = self.comments.clone();
+        transaction.comments.append(&mut clock_out.comments.clone());
+
+        let seconds = (clock_out.datetime - self.datetime).num_seconds();
+        let mut posting = RawPosting::new();
+        posting.account = account;
+        posting.date = transaction.date;
+        posting.kind = PostingType::Virtual;
+        posting.payee = transaction.payee.clone();
+        posting.money_amount = Some(BigRational::new(BigInt::from(seconds), BigInt::from(3600)));
+        posting.money_currency = Some(TIMECLOCK_COMMODITY.to_string());
+        transaction.postings.borrow_mut().push(posting);
+        transaction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use structopt::StructOpt;
+
+    use super::*;
+    use crate::CommonOpts;
+
+    #[test]
+    fn clock_in_and_out() {
+        let mut tokenizer = Tokenizer::from(
+            "i 2021/03/01 09:00:00 Client:Project  Consulting ; :billable:
+o 2021/03/01 12:30:00
+i 2021/03/02 22:00 Client:Other project
+o 2021/03/03 00:15:30 Client:Other project
+"
+            .to_string(),
+        );
+        let items = tokenizer.tokenize(&CommonOpts::from_iter(["", "-f", ""].iter()));
+        assert_eq!(items.transactions.len(), 2);
+
+        let transaction = &items.transactions[0];
+        assert_eq!(transaction.description, "Consulting");
+        assert_eq!(transaction.comments.len(), 1);
+        let posting = &transaction.postings.borrow()[0];
+        assert_eq!(posting.account, "Client:Project");
+        assert_eq!(posting.kind, PostingType::Virtual);
+        assert_eq!(
+            posting.money_amount,
+            Some(BigRational::new(BigInt::from(7), BigInt::from(2)))
+        );
+
+        let transaction = &items.transactions[1];
+        assert_eq!(transaction.description, "Client:Other project");
+        assert_eq!(
+            transaction.postings.borrow()[0].money_amount,
+            Some(BigRational::new(BigInt::from(8130), BigInt::from(3600)))
+        );
+        assert!(items.commodities.get(TIMECLOCK_COMMODITY).is_ok());
+    }
+}
@@ -1,17 +1,26 @@
 use super::super::Rule;
-use crate::models::{Cleared, Comment, PostingType, PriceType, Transaction, TransactionType};
-use crate::parser::utils::{parse_date, parse_rational, parse_string};
-use crate::parser::Tokenizer;
+use crate::models::{
+    Cleared, Comment, Currency, PostingType, PriceType, SourceLocation, Transaction,
+    TransactionType,
+};
+use crate::parser::utils::{parse_date, parse_decimal, parse_string};
+use crate::parser::{decimal_mark_of, Tokenizer};
+use crate::List;
 use chrono::NaiveDate;
 use num::{rational::BigRational, BigInt};
 use pest::iterators::Pair;
 
 impl<'a> Tokenizer<'a> {
     /// Parses a transaction
-    pub(crate) fn parse_transaction(&self, element: Pair<Rule>) -> Transaction<RawPosting> {
+    pub(crate) fn parse_transaction(
+        &self,
+        element: Pair<Rule>,
+        commodities: &List<Currency>,
+    ) -> Transaction<RawPosting> {
         let mut transaction = Transaction::<RawPosting>::new(match element.as_rule() {
             Rule::transaction => TransactionType::Real,
             Rule::automated_transaction => TransactionType::Automated,
+            Rule::periodic_transaction => TransactionType::Periodic,
             x => panic!("{:?}", x),
         });
 
@@ -31,12 +40,9 @@ impl<'a> Tokenizer<'a> {
                     transaction.effective_date =
                         Some(parse_date(part.into_inner().next().unwrap()));
                 }
-                Rule::status => {
-                    transaction.cleared = match part.as_str() {
-                        "!" => Cleared::NotCleared,
-                        "*" => Cleared::Cleared,
-                        x => panic!("Found '{}', expected '!' or '*'", x),
-                    }
+                Rule::status => transaction.cleared = parse_status(part),
+                Rule::period_expression => {
+                    transaction.period = Some(part.as_str().trim().to_string())
                 }
                 Rule::code => {
                     let mut code = part.as_str().chars();
@@ -74,10 +80,18 @@ impl<'a> Tokenizer<'a> {
         //
         for part in parsed_transaction {
             match part.as_rule() {
-                Rule::posting | Rule::automated_posting => transaction
-                    .postings
-                    .borrow_mut()
-                    .push(parse_posting(part, &transaction.payee, &transaction.date)),
+                Rule::posting | Rule::automated_posting => {
+                    let line = self.line_of(part.as_span().start());
+                    let mut posting =
+                        parse_posting(part, &transaction.payee, &transaction.date, |currency| {
+                            decimal_mark_of(commodities, currency, self.decimal_mark)
+                        });
+                    posting.source = self.file.map(|file| SourceLocation {
+                        file: file.clone(),
+                        line,
+                    });
+                    transaction.postings.borrow_mut().push(posting)
+                }
                 Rule::comment => transaction.comments.push(Comment::from(parse_string(
                     part.into_inner().next().unwrap(),
                 ))),
@@ -101,6 +115,15 @@ pub struct RawPosting {
     pub cost_currency: Option<String>,
     pub cost_format: Option<String>,
     pub cost_type: Option<PriceType>,
+    pub cost_expr: Option<String>,
+    /// Virtual costs do not go to the price database
+    pub virtual_cost: bool,
+    /// The price of the lot, `{P}` or `{=P}`, kept apart from the `@` cost
+    pub lot_amount: Option<BigRational>,
+    pub lot_currency: Option<String>,
+    pub lot_format: Option<String>,
+    /// Fixated lot prices, `{=P}`, do not go to the price database either
+    pub lot_fixated: bool,
     pub balance_amount: Option<BigRational>,
     pub balance_currency: Option<String>,
     pub balance_format: Option<String>,
@@ -108,10 +131,13 @@ pub struct RawPosting {
     pub amount_expr: Option<String>,
     pub kind: PostingType,
     pub payee: Option<String>,
+    /// The status of the posting, if it is different from the one of the transaction
+    pub cleared: Cleared,
+    pub source: Option<SourceLocation>,
 }
 
 impl RawPosting {
-    fn new() -> RawPosting {
+    pub(crate) fn new() -> RawPosting {
         RawPosting {
             account: String::new(),
             date: None,
@@ -120,12 +146,20 @@ impl RawPosting {
             cost_amount: None,
             cost_currency: None,
             cost_type: None,
+            cost_expr: None,
+            virtual_cost: false,
+            lot_amount: None,
+            lot_currency: None,
+            lot_format: None,
+            lot_fixated: false,
             balance_amount: None,
             balance_currency: None,
             comments: vec![],
             amount_expr: None,
             kind: PostingType::Real,
             payee: None,
+            cleared: Cleared::Unknown,
+            source: None,
             money_format: None,
             cost_format: None,
             balance_format: None,
@@ -133,17 +167,28 @@ impl RawPosting {
     }
 }
 
+/// Parses the status mark of a transaction or a posting
+fn parse_status(status: Pair<Rule>) -> Cleared {
+    match status.as_str() {
+        "!" => Cleared::Pending,
+        "*" => Cleared::Cleared,
+        x => panic!("Found '{}', expected '!' or '*'", x),
+    }
+}
+
 /// Parses a posting
 fn parse_posting(
     raw: Pair<Rule>,
     default_payee: &Option<String>,
     default_date: &Option<NaiveDate>,
+    decimal_mark: impl Fn(&str) -> char,
 ) -> RawPosting {
     let mut posting = RawPosting::new();
     let elements = raw.into_inner();
     for part in elements {
         let rule = part.as_rule();
         match rule {
+            Rule::status => posting.cleared = parse_status(part),
             Rule::posting_kind => {
                 let kind = part.into_inner().next().unwrap();
                 posting.kind = match kind.as_rule() {
@@ -153,13 +198,32 @@ fn parse_posting(
                 };
                 posting.account = kind.into_inner().next().unwrap().as_str().to_string();
             }
-            Rule::amount | Rule::cost | Rule::balance => {
-                let cost_type = if part.as_str().starts_with("@@") {
+            Rule::amount | Rule::cost | Rule::balance | Rule::lot_price => {
+                let text = part.as_str();
+                let cost_type = if text.starts_with("@@") | text.starts_with("(@@)") {
                     Some(PriceType::Total)
                 } else {
                     Some(PriceType::PerUnit)
                 };
+                match rule {
+                    Rule::cost => posting.virtual_cost = text.starts_with("(@"),
+                    Rule::lot_price => posting.lot_fixated = text.starts_with("{="),
+                    _ => {}
+                }
                 let mut inner = part.into_inner();
+                if let Some(Rule::value_expr) = inner.peek().map(|x| x.as_rule()) {
+                    // The amount is an expression, it is evaluated when building the ledger
+                    let expression = inner.next().unwrap().as_str().to_string();
+                    match rule {
+                        Rule::amount => posting.amount_expr = Some(expression),
+                        Rule::cost => {
+                            posting.cost_expr = Some(expression);
+                            posting.cost_type = cost_type;
+                        }
+                        x => panic!("Expected amount or cost {:?}", x),
+                    }
+                    continue;
+                }
                 let negative = inner.as_str().starts_with('-');
                 let mut money = inner.next().unwrap().into_inner();
                 let money_format = money.as_str().to_string();
@@ -168,16 +232,15 @@ fn parse_posting(
                 match money.next() {
                     Some(money_part) => match money_part.as_rule() {
                         Rule::number => {
-                            amount = parse_rational(money_part);
-                            currency = Some(parse_string(money.next().unwrap()));
+                            let name = parse_string(money.next().unwrap());
+                            amount = parse_decimal(money_part, decimal_mark(&name));
+                            currency = Some(name);
                         }
                         Rule::currency => {
-                            currency = Some(parse_string(money_part));
-                            if negative {
-                                amount = -parse_rational(money.next().unwrap());
-                            } else {
-                                amount = parse_rational(money.next().unwrap());
-                            }
+                            let name = parse_string(money_part);
+                            let number = parse_decimal(money.next().unwrap(), decimal_mark(&name));
+                            amount = if negative { -number } else { number };
+                            currency = Some(name);
                         }
                         _ => amount = BigRational::new(BigInt::from(0), BigInt::from(1)),
                     },
@@ -196,6 +259,11 @@ fn parse_posting(
                         posting.cost_type = cost_type;
                         posting.cost_format = Some(money_format);
                     }
+                    Rule::lot_price => {
+                        posting.lot_amount = Some(amount);
+                        posting.lot_currency = currency;
+                        posting.lot_format = Some(money_format);
+                    }
                     Rule::balance => {
                         posting.balance_amount = Some(amount);
                         posting.balance_currency = currency;
@@ -253,7 +321,7 @@ mod tests {
 
         let parsed = tokenizer.tokenize(&CommonOpts::from_iter(["", "-f", ""].iter()));
         let transaction = &parsed.transactions[0];
-        assert_eq!(transaction.cleared, Cleared::NotCleared);
+        assert_eq!(transaction.cleared, Cleared::Pending);
         assert_eq!(transaction.status, TransactionStatus::NotChecked);
         assert_eq!(transaction.code, Some(String::from("8760")));
         assert_eq!(transaction.payee, Some(String::from("EstateGuru")));
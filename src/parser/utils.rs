@@ -1,7 +1,9 @@
 //! This module contains auxiliary parsers
 
 use super::{GrammarParser, Rule};
-use chrono::NaiveDate;
+use crate::models::{Currency, Money};
+use crate::List;
+use chrono::{NaiveDate, NaiveTime};
 use num::{BigInt, BigRational};
 use pest::iterators::Pair;
 
@@ -32,13 +34,58 @@ pub(crate) fn parse_date(date: Pair<Rule>) -> NaiveDate {
     NaiveDate::from_ymd(year, month, day)
 }
 
+/// Parses a time, fractions of a second are ignored
+pub(crate) fn parse_time(time: Pair<Rule>) -> NaiveTime {
+    // Assume time is a Rule::time
+    let mut parsed = time.into_inner();
+    let hour = u32::from_str(parsed.next().unwrap().as_str()).unwrap();
+    let minute = u32::from_str(parsed.next().unwrap().as_str()).unwrap();
+    let second = match parsed.next() {
+        Some(second) => u32::from_str(second.as_str().split('.').next().unwrap()).unwrap(),
+        None => 0,
+    };
+    NaiveTime::from_hms_opt(hour, minute, second).unwrap()
+}
+
 pub(crate) fn parse_rational(number: Pair<Rule>) -> BigRational {
+    parse_decimal(number, '.')
+}
+
+/// Whether a number is unambiguous with the given decimal mark
+///
+/// The other separator can only group the digits of the integer part in groups of three, so
+/// `1,234.56` is valid with a decimal point but `1,5` and `200,00` are not.
+pub(crate) fn valid_number(text: &str, decimal_mark: char) -> bool {
+    let grouping = match decimal_mark {
+        '.' => ',',
+        _ => '.',
+    };
+    let text = text.trim_start_matches('-');
+    let mut parts = text.split(decimal_mark);
+    let integer = parts.next().unwrap();
+    if let Some(decimals) = parts.next() {
+        if decimals.contains(grouping) | parts.next().is_some() {
+            return false;
+        }
+    }
+    if !integer.contains(grouping) {
+        return true;
+    }
+    let mut groups = integer.split(grouping);
+    let first = groups.next().unwrap();
+    (1..=3).contains(&first.len()) && groups.all(|x| x.len() == 3)
+}
+
+/// Parses a number with the given decimal mark, the other separators group digits
+pub(crate) fn parse_decimal(number: Pair<Rule>, decimal_mark: char) -> BigRational {
     let mut num = String::new();
     let mut den = "1".to_string();
     let mut decimal = false;
     for c in number.as_str().chars() {
-        if c == '.' {
+        if c == decimal_mark {
             decimal = true
+        } else if (c == '.') | (c == ',') {
+            // digit grouping
         } else {
             num.push(c);
             if decimal {
@@ -52,6 +99,37 @@ pub(crate) fn parse_rational(number: Pair<Rule>) -> BigRational {
     )
 }
 
+/// Parses an amount given in the command line, like `1234.56 EUR`, of a known commodity
+pub(crate) fn parse_money(text: &str, commodities: &List<Currency>) -> Option<Money> {
+    let money = match GrammarParser::parse(Rule::money, text.trim()) {
+        Ok(mut parsed) if parsed.as_str() == text.trim() => parsed.next().unwrap(),
+        _ => return None,
+    };
+    let mut number = None;
+    let mut currency = None;
+    for part in money.into_inner() {
+        match part.as_rule() {
+            Rule::number if !valid_number(part.as_str(), '.') => return None,
+            Rule::number => number = Some(parse_decimal(part, '.')),
+            Rule::currency => currency = Some(parse_string(part)),
+            _ => {}
+        }
+    }
+    match (number, currency) {
+        (Some(number), Some(currency)) => {
+            let (currency, negative) = match currency.strip_prefix('-') {
+                Some(name) => (name.to_string(), true),
+                None => (currency, false),
+            };
+            let currency = commodities.get(&currency).ok()?.clone();
+            let number = if negative { -number } else { number };
+            Some(Money::from((currency, number)))
+        }
+        (Some(number), None) if number == num::zero() => Some(Money::Zero),
+        _ => None,
+    }
+}
+
 pub(crate) fn parse_string(string: Pair<Rule>) -> String {
     match string.as_rule() {
         Rule::string => {
@@ -75,7 +153,7 @@ pub(crate) fn parse_string(string: Pair<Rule>) -> String {
 }
 
 /// Counts the number of decimals in an amount as defined in the grammar
-pub(crate) fn count_decimals(amount: &str) -> usize {
+pub(crate) fn count_decimals(amount: &str, decimal_mark: char) -> usize {
     let mut parsed = GrammarParser::parse(Rule::money, amount)
         .unwrap()
         .next()
@@ -93,21 +171,66 @@ pub(crate) fn count_decimals(amount: &str) -> usize {
 
     let text = number.as_str();
     // dbg!(text);
-    if text.contains('.') {
-        number.as_str().split('.').last().unwrap().len()
+    if text.contains(decimal_mark) {
+        number.as_str().split(decimal_mark).last().unwrap().len()
     } else {
         0
     }
 }
 
+/// Finds the currencies used in a value expression, along with the format of the amount they came with
+pub(crate) fn expression_currencies(expression: &str) -> Vec<(Option<String>, Option<String>)> {
+    let mut currencies = vec![];
+    if let Ok(parsed) = GrammarParser::parse(Rule::value_expr, expression) {
+        for pair in parsed.flatten() {
+            if pair.as_rule() != Rule::money {
+                continue;
+            }
+            let format = pair.as_str().to_string();
+            if let Some(currency) = pair.into_inner().find(|x| x.as_rule() == Rule::currency) {
+                currencies.push((Some(parse_string(currency)), Some(format)));
+            }
+        }
+    }
+    currencies
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::parser::utils::count_decimals;
+    use crate::parser::utils::{count_decimals, expression_currencies, valid_number};
+
+    #[test]
+    fn ambiguous_numbers() {
+        assert!(valid_number("1,234.56", '.'));
+        assert!(valid_number("-1,234,567", '.'));
+        assert!(valid_number("1.5", '.'));
+        assert!(!valid_number("1,5", '.'));
+        assert!(!valid_number("200,00", '.'));
+        assert!(!valid_number("1,2345.6", '.'));
+        assert!(!valid_number("1.234.5", '.'));
+        assert!(valid_number("200,00", ','));
+        assert!(valid_number("1.234,56", ','));
+        assert!(!valid_number("1.5", ','));
+        assert!(!valid_number("1,234.5", ','));
+    }
 
     #[test]
     fn count_decimals_test() {
-        assert_eq!(count_decimals("150.4 EUR"), 1);
-        assert_eq!(count_decimals("150 EUR"), 0);
-        assert_eq!(count_decimals("EUR 150.4"), 1);
+        assert_eq!(count_decimals("150.4 EUR", '.'), 1);
+        assert_eq!(count_decimals("150 EUR", '.'), 0);
+        assert_eq!(count_decimals("EUR 150.4", '.'), 1);
+        assert_eq!(count_decimals("1,150.40 EUR", '.'), 2);
+        assert_eq!(count_decimals("1.150,4 EUR", ','), 1);
+        assert_eq!(count_decimals("1.150 EUR", ','), 0);
+    }
+
+    #[test]
+    fn expression_currencies_test() {
+        let currencies = expression_currencies("(84.30 EUR / 3 + USD 1)");
+        assert_eq!(currencies.len(), 2);
+        assert_eq!(currencies[0].0, Some("EUR".to_string()));
+        assert_eq!(currencies[0].1, Some("84.30 EUR".to_string()));
+        assert_eq!(currencies[1].0, Some("USD".to_string()));
+        assert!(expression_currencies("(amount * 0.10)").is_empty());
     }
 }
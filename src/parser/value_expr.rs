@@ -1,11 +1,12 @@
 use super::utils::parse_rational;
 use super::{GrammarParser, Rule};
 use crate::app;
+use crate::error::LedgerError;
 use crate::models::{Account, Currency, Money, Payee, Posting, Transaction};
 use crate::List;
 use chrono::NaiveDate;
 
-use num::{abs, BigRational};
+use num::{abs, BigRational, Zero};
 use pest::Parser;
 use regex::Regex;
 use std::borrow::Borrow;
@@ -38,7 +39,7 @@ pub fn eval_expression(
     transaction: &Transaction<Posting>,
     commodities: &mut List<Currency>,
     regexes: &mut HashMap<String, Regex>,
-) -> EvalResult {
+) -> Result<EvalResult, LedgerError> {
     let parsed = GrammarParser::parse(Rule::value_expr, expression)
         .expect("unsuccessful parse") // unwrap the parse result
         .next()
@@ -58,14 +59,34 @@ pub fn eval_value_expression(
     transaction: &Transaction<Posting>,
     commodities: &mut List<Currency>,
     regexes: &mut HashMap<String, Regex>,
-) -> Money {
-    match eval_expression(expression, posting, transaction, commodities, regexes) {
-        EvalResult::Number(n) => posting.amount.clone().unwrap() * n,
-        EvalResult::Money(m) => m,
-        x => {
-            eprintln!("Found {:?}.", x);
-            panic!("Should be money.");
-        }
+) -> Result<Money, LedgerError> {
+    match eval_expression(expression, posting, transaction, commodities, regexes)? {
+        EvalResult::Number(n) => match &posting.amount {
+            Some(amount) => Ok(amount.clone() * n),
+            None => Err(LedgerError::MissingAmount(expression.to_string())),
+        },
+        EvalResult::Money(m) => Ok(m),
+        _ => Err(LedgerError::ExpressionIsNotMoney(expression.to_string())),
+    }
+}
+
+/// Evaluates the expression of a posting amount or cost, which has to result in money
+pub fn eval_money_expression(
+    expression: &str,
+    posting: &Posting,
+    transaction: &Transaction<Posting>,
+    commodities: &List<Currency>,
+    regexes: &mut HashMap<String, Regex>,
+) -> Result<Money, LedgerError> {
+    let root = build_root_node_from_expression(expression, regexes);
+    let result = eval(&root, posting, transaction, commodities, regexes).map_err(|e| match e {
+        // The posting whose amount is being computed has none yet
+        LedgerError::MissingAmount(_) => LedgerError::MissingAmount(expression.to_string()),
+        e => e,
+    })?;
+    match result {
+        EvalResult::Money(m) => Ok(m),
+        _ => Err(LedgerError::ExpressionIsNotMoney(expression.to_string())),
     }
 }
 
@@ -73,6 +94,7 @@ pub fn eval_value_expression(
 pub enum Node {
     Amount,
     Account,
+    AccountType,
     Payee,
     Note,
     Date,
@@ -171,10 +193,19 @@ pub fn eval(
     transaction: &Transaction<Posting>,
     commodities: &List<Currency>,
     regexes: &mut HashMap<String, Regex>,
-) -> EvalResult {
+) -> Result<EvalResult, LedgerError> {
     let res = match node {
-        Node::Amount => EvalResult::Money(posting.amount.clone().unwrap()),
+        Node::Amount => match &posting.amount {
+            Some(amount) => EvalResult::Money(amount.clone()),
+            None => return Err(LedgerError::MissingAmount(String::from("amount"))),
+        },
         Node::Account => EvalResult::Account(posting.account.clone()),
+        Node::AccountType => EvalResult::String(
+            posting
+                .account
+                .get_account_type()
+                .map(|account_type| account_type.to_string()),
+        ),
         Node::Payee => EvalResult::Payee(posting.payee.clone().unwrap()),
         Node::Note => EvalResult::Note,
         Node::Date => EvalResult::Date(posting.date),
@@ -191,7 +222,7 @@ pub fn eval(
             EvalResult::Money(Money::from((cur, amount.clone())))
         }
         Node::UnaryExpr { op, child } => {
-            let res = eval(child, posting, transaction, commodities, regexes);
+            let res = eval(child, posting, transaction, commodities, regexes)?;
             match op {
                 Unary::Not => match res {
                     EvalResult::Boolean(b) => EvalResult::Boolean(!b),
@@ -204,7 +235,7 @@ pub fn eval(
                         //     continue;
                         // }
                         if let EvalResult::Boolean(b) =
-                            eval(child, p, transaction, commodities, regexes)
+                            eval(child, p, transaction, commodities, regexes)?
                         {
                             if b {
                                 res = true;
@@ -249,8 +280,8 @@ pub fn eval(
             }
         }
         Node::BinaryExpr { op, lhs, rhs } => {
-            let left = eval(lhs, posting, transaction, commodities, regexes);
-            let right = eval(rhs, posting, transaction, commodities, regexes);
+            let left = eval(lhs, posting, transaction, commodities, regexes)?;
+            let right = eval(rhs, posting, transaction, commodities, regexes)?;
             match op {
                 Binary::Eq => match right {
                     EvalResult::Regex(rhs) => match left {
@@ -291,9 +322,15 @@ pub fn eval(
                         }
                     } else if let EvalResult::Money(lhs) = left {
                         if let EvalResult::Money(rhs) = right {
+                            let error =
+                                || LedgerError::MixedCommodities(format!("{} and {}", lhs, rhs));
                             EvalResult::Money(match op {
-                                Binary::Add => (lhs + rhs).to_money().unwrap(),
-                                Binary::Subtract => (lhs - rhs).to_money().unwrap(),
+                                Binary::Add => (lhs.clone() + rhs.clone())
+                                    .to_money()
+                                    .map_err(|_| error())?,
+                                Binary::Subtract => (lhs.clone() - rhs.clone())
+                                    .to_money()
+                                    .map_err(|_| error())?,
                                 _ => unreachable!(),
                             })
                         } else {
@@ -308,6 +345,9 @@ pub fn eval(
                         if let EvalResult::Number(rhs) = right {
                             EvalResult::Number(match op {
                                 Binary::Mult => lhs * rhs,
+                                Binary::Div if rhs.is_zero() => {
+                                    return Err(LedgerError::DivisionByZero)
+                                }
                                 Binary::Div => lhs / rhs,
                                 _ => unreachable!(),
                             })
@@ -324,6 +364,9 @@ pub fn eval(
                         if let EvalResult::Number(rhs) = right {
                             EvalResult::Money(match op {
                                 Binary::Mult => lhs * rhs,
+                                Binary::Div if rhs.is_zero() => {
+                                    return Err(LedgerError::DivisionByZero)
+                                }
                                 Binary::Div => lhs / rhs,
                                 _ => unreachable!(),
                             })
@@ -364,7 +407,7 @@ pub fn eval(
             }
         }
     };
-    res
+    Ok(res)
 }
 
 #[derive(Clone, Debug)]
@@ -410,31 +453,30 @@ fn build_ast_from_expr(
         | Rule::multiplicative_expr => {
             let mut pair = pair.into_inner();
             let lhspair = pair.next().unwrap();
-            let lhs = build_ast_from_expr(lhspair, regexes);
-            match pair.next() {
-                None => lhs,
-                Some(x) => {
-                    let op = match rule {
-                        Rule::or_expr => Binary::Or,
-                        Rule::and_expr => Binary::And,
-                        _ => match x.as_str() {
-                            "+" => Binary::Add,
-                            "-" => Binary::Subtract,
-                            "*" => Binary::Mult,
-                            "/" => Binary::Div,
-                            "=~" | "==" => Binary::Eq,
-                            "<" => Binary::Lt,
-                            ">" => Binary::Gt,
-                            "<=" => Binary::Le,
-                            ">=" => Binary::Ge,
-                            x => unreachable!("{}", x),
-                        },
-                    };
-                    let rhspair = pair.next().unwrap();
-                    let rhs = build_ast_from_expr(rhspair, regexes);
-                    parse_binary_expr(op, lhs, rhs)
-                }
+            let mut lhs = build_ast_from_expr(lhspair, regexes);
+            // Operators of the same precedence are applied from left to right
+            while let Some(x) = pair.next() {
+                let op = match rule {
+                    Rule::or_expr => Binary::Or,
+                    Rule::and_expr => Binary::And,
+                    _ => match x.as_str() {
+                        "+" => Binary::Add,
+                        "-" => Binary::Subtract,
+                        "*" => Binary::Mult,
+                        "/" => Binary::Div,
+                        "=~" | "==" => Binary::Eq,
+                        "<" => Binary::Lt,
+                        ">" => Binary::Gt,
+                        "<=" => Binary::Le,
+                        ">=" => Binary::Ge,
+                        x => unreachable!("{}", x),
+                    },
+                };
+                let rhspair = pair.next().unwrap();
+                let rhs = build_ast_from_expr(rhspair, regexes);
+                lhs = parse_binary_expr(op, lhs, rhs);
             }
+            lhs
         }
         Rule::primary => {
             let mut inner = pair.into_inner();
@@ -497,6 +539,7 @@ fn build_ast_from_expr(
                 }
                 Rule::variable => match first.as_str() {
                     "account" => Node::Account,
+                    "account_type" => Node::AccountType,
                     "amount" => Node::Amount,
                     "payee" => Node::Payee,
                     "note" => Node::Note,
@@ -336,7 +336,7 @@ fn no_config_file() {
 }
 
 #[test]
-#[should_panic()]
+#[should_panic]
 /// Check that pedantic works
 fn pedantic() {
     let args_1 = &["bal", "-f", "tests/example_files/demo.ledger", "--pedantic"];
@@ -354,6 +354,55 @@ fn stats() {
     test_args(args_1);
 }
 
+#[test]
+/// The stats command accepts a query and reports by account, payee and commodity
+fn stats_by_account() {
+    let args = [
+        "stats",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/status.ledger",
+        "Expenses",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let line = |text: &str| {
+        output
+            .lines()
+            .find(|x| x.contains(text))
+            .unwrap()
+            .to_string()
+    };
+    assert!(line("postings").starts_with("3 postings"));
+    assert!(line("uncleared").starts_with("1 uncleared"));
+    assert!(line("pending").starts_with("1 pending"));
+    assert!(line("Expenses:Food").contains("      2         1"));
+    assert!(output
+        .lines()
+        .any(|x| x.contains("Expenses:Rent") & x.contains("500 EUR")));
+    assert!(output.contains("190 EUR"));
+    assert!(!output.contains("Assets:Bank"));
+
+    // A journal without transactions
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args([
+            "stats",
+            "--init-file",
+            "tests/example_files/empty_ledgerrc",
+            "-f",
+            "-",
+        ])
+        .write_stdin("account Assets:Bank\ncommodity EUR\n")
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("0 transactions"));
+    assert!(output.contains("Unused accounts:\n\tAssets:Bank"));
+    assert!(output.contains("Unused commodities:\n\tEUR"));
+}
+
 #[test]
 /// Check the collapse option
 fn collapse() {
@@ -561,3 +610,1220 @@ fn balance_convert() {
     assert!(output.lines().into_iter().count() > 10);
     test_args(args);
 }
+
+#[test]
+fn timeclock() {
+    let args = &[
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/timeclock.ledger",
+        "-p",
+        "2021-03",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("3.50 h"));
+    assert!(output.contains("4.75 h"));
+    assert!(!output.contains("12.75 h"));
+    test_args(args);
+}
+
+#[test]
+fn hledger_directives() {
+    let args = &[
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/hledger.ledger",
+        "type:AL",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("2.489,25 EUR"));
+    assert!(output.contains("Activos:Banco"));
+    assert!(output.contains("-85,20 EUR"));
+    assert!(!output.contains("Gastos"));
+    test_args(args);
+
+    // Full names are types too, and unknown types are an error
+    let mut args = args.to_vec();
+    args[5] = "type:Asset";
+    let assert = Command::cargo_bin("dinero").unwrap().args(&args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("Activos:Banco"));
+    assert!(!output.contains("Pasivos"));
+    args[5] = "type:AY";
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(&args)
+        .assert()
+        .failure();
+    let output = String::from_utf8(assert.get_output().to_owned().stderr).unwrap();
+    assert!(output.contains("Unknown account type"));
+}
+
+#[test]
+/// A comma is only digit grouping in groups of three, otherwise the amount is an error
+fn ambiguous_amounts() {
+    let args = [
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "-",
+    ];
+    for amount in ["1,5 EUR", "200,00 EUR"].iter() {
+        let assert = Command::cargo_bin("dinero")
+            .unwrap()
+            .args(args)
+            .write_stdin(format!("2021-01-01 Test\n    A    {}\n    B\n", amount))
+            .assert()
+            .failure();
+        let output = String::from_utf8(assert.get_output().to_owned().stderr).unwrap();
+        assert!(output.contains("ambiguous"));
+    }
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .write_stdin("2021-01-01 Test\n    A    1,234.50 EUR\n    B\n")
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("1,234.50 EUR"));
+
+    // A commodity declared with a format has its decimal mark
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .write_stdin(
+            "commodity 1.000,00 EUR
+P 2021-01-01 USD 0,90 EUR
+2021-01-02 Test
+    A    1.234,50 EUR
+    A    1,5 EUR
+    A    10.5 USD
+    B
+",
+        )
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("1.236,00 EUR"));
+    assert!(output.contains("10.5 USD"));
+}
+
+#[test]
+fn financial_statements() {
+    for command in ["bs", "is", "cf"].iter() {
+        let args = &[
+            command,
+            "--init-file",
+            "tests/example_files/empty_ledgerrc",
+            "-f",
+            "tests/example_files/hledger.ledger",
+            "-M",
+        ];
+        test_args(args);
+    }
+    let args = &[
+        "is",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/hledger.ledger",
+        "-X",
+        "EUR",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    // Revenues are shown positive, and so is the net income
+    assert!(output.contains(" 2.500,50 EUR |"));
+    assert!(output.contains(" 2.404,05 EUR |"));
+    test_args(args);
+
+    let args = &[
+        "bs",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/demo.ledger",
+        "--assets-regex",
+        "^assets:bank",
+        "--no-total",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("Checking account"));
+    assert!(!output.contains("Shares"));
+    assert!(!output.contains("Total"));
+}
+
+#[test]
+fn fixated_lots_are_not_revalued() {
+    let args = [
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "-",
+        "Shares",
+        "-X",
+        "USD",
+    ];
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .write_stdin(
+            "2021-01-01 Buy
+    Assets:Shares    10 ACME {=50 USD}
+    Assets:Bank
+2021-01-02 Buy
+    Assets:Shares    10 ACME @ 70 USD
+    Assets:Bank
+P 2021-02-01 ACME 80 USD
+",
+        )
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    // 10 ACME at the lot price and 10 ACME at the market price
+    assert!(output.contains("1,300 USD"));
+}
+
+#[test]
+fn equity() {
+    let args = &[
+        "equity",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/lots.ledger",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    // Only the last lot of shares is left
+    assert!(output.contains("2021-05-01 Closing balances"));
+    assert!(output.contains("-3 ACME {=60.00 USD}"));
+    assert!(output.contains("2021-05-02 Opening balances"));
+    assert!(output.contains(" 1040.00 USD"));
+
+    // The output is a valid journal
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args([
+            "bal",
+            "--init-file",
+            "tests/example_files/empty_ledgerrc",
+            "-f",
+            "-",
+            "Opening",
+        ])
+        .write_stdin(output)
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("-220.00 USD"));
+
+    let args = &[
+        "close",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/lots.ledger",
+        "-X",
+        "USD",
+        "-e",
+        "2021-12-31",
+        "--opening-account",
+        "Equity:Start",
+        "Assets",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("2021-12-31 Opening balances"));
+    assert!(output.contains("240.00 USD"));
+    assert!(output.contains("Equity:Start"));
+    test_args(args);
+}
+
+#[test]
+fn equity_round_trip() {
+    // The display format of euros can't be read back as an amount
+    let file = "tests/example_files/equity.ledger";
+    let balance = |args: &[&str], input: &str| {
+        let assert = Command::cargo_bin("dinero")
+            .unwrap()
+            .args(["bal", "--init-file", "tests/example_files/empty_ledgerrc"])
+            // One commodity per line, as the commodities of a balance are not sorted
+            .args(["--flat", "--no-total"])
+            .args(args)
+            .write_stdin(input)
+            .assert()
+            .success();
+        String::from_utf8(assert.get_output().to_owned().stdout).unwrap()
+    };
+    let original = balance(&["-f", file], "");
+    assert!(original.contains("1.176,75 €"));
+
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args([
+            "equity",
+            "--init-file",
+            "tests/example_files/empty_ledgerrc",
+            "-f",
+            file,
+        ])
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("-1176.75 €"));
+    assert!(output.contains("10 ACME {=12.25 €}"));
+
+    // With the same commodity declaration, the opening balances are the original ones
+    let declaration: String = std::fs::read_to_string(file)
+        .unwrap()
+        .lines()
+        .take_while(|x| !x.is_empty())
+        .map(|x| format!("{}\n", x))
+        .collect();
+    let reopened = balance(
+        &["-f", "-", "-b", "2021-02-11"],
+        &format!("{}\n{}", declaration, output),
+    );
+    assert_eq!(reopened, original);
+}
+
+#[test]
+fn tags_and_codes() {
+    let args = &[
+        "tags",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--values",
+        "--strict",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let stderr = String::from_utf8(assert.get_output().to_owned().stderr).unwrap();
+    assert_eq!(output.lines().count(), 5);
+    assert!(output.contains("project"));
+    assert!(output.contains("  Alpha"));
+    assert!(stderr.contains("tag billable not declared"));
+    assert!(!stderr.contains("tag project not declared"));
+
+    let args = &[
+        "codes",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--date-format",
+        "%Y-%m-%d",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("2  2021-01-05  2021-03-15"));
+    test_args(args);
+}
+
+#[test]
+fn pivot() {
+    let args = &[
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--pivot",
+        "project",
+        "--flat",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("(none)"));
+    assert!(output.contains("Alpha:Design"));
+    assert!(!output.contains("Assets"));
+    test_args(args);
+
+    // Hierarchical values roll up
+    let args = &[
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--pivot",
+        "project",
+        "Expenses",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("Alpha:Design"));
+    assert!(!output.contains("(none)"));
+
+    let args = &[
+        "reg",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--pivot",
+        "project",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("Beta"));
+    assert!(!output.contains("Income"));
+    test_args(args);
+}
+
+#[test]
+fn format_templates() {
+    let args = &[
+        "reg",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--date-format",
+        "%Y-%m-%d",
+        "--register-format",
+        "%(date)|%-10.6(payee)|%8(amount)|%(total)|%(tag('project'))",
+        "Bank",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "2021-01-05|Client    | 100 EUR|100 EUR|Alpha");
+    assert_eq!(lines[3], "2021-04-20|Design    | -30 EUR|320 EUR|");
+
+    let args = &[
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--flat",
+        "--format",
+        "%(account);%(total)",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("Assets:Bank;320 EUR\n"));
+    assert!(output.ends_with(";0\n"));
+
+    // Value expressions need a posting
+    let args = &[
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--balance-format",
+        "%(payee)",
+    ];
+    test_err(args);
+}
+
+#[test]
+fn sort_display_and_limits() {
+    // Sorted by amount, the running total follows the new order
+    let args = &[
+        "reg",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--register-format",
+        "%(payee)|%(amount)|%(total)",
+        "--sort",
+        "-amount",
+        "--head",
+        "2",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert_eq!(
+        output,
+        "Client B|200 EUR|200 EUR\nClient A|100 EUR|300 EUR\n"
+    );
+
+    // Hidden rows still count for the running total
+    let args = &[
+        "reg",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--register-format",
+        "%(account)|%(total)",
+        "--display",
+        "account =~ /Income/",
+        "--tail",
+        "1",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert_eq!(output, "Income:Consulting|0\n");
+
+    let args = &[
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--flat",
+        "--balance-format",
+        "%(account)|%(total)",
+        "-S",
+        "amount",
+        "--display",
+        "not account =~ /Design/",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert_eq!(
+        output,
+        "Income:Consulting|-350 EUR\nAssets:Bank|320 EUR\n|0\n"
+    );
+
+    // Expressions that don't parse are errors
+    let args = &[
+        "reg",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--sort",
+        "foo(",
+    ];
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .assert()
+        .failure();
+    let output = String::from_utf8(assert.get_output().to_owned().stderr).unwrap();
+    assert!(output.contains("Invalid expression"));
+
+    // There are no postings to evaluate the value expressions of a balance report against
+    let args = &[
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--balance-format",
+        "%(account)|%(abs(amount))",
+    ];
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .assert()
+        .failure();
+    let output = String::from_utf8(assert.get_output().to_owned().stderr).unwrap();
+    assert!(output.contains("%(abs(amount))"));
+    assert!(assert.get_output().stdout.is_empty());
+
+    let args = &[
+        "accounts",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--sort",
+        "account",
+        "--head",
+        "1",
+    ];
+    test_args(args);
+    let args = &[
+        "payees",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/metadata.ledger",
+        "--display",
+        "payee =~ /Client/",
+        "--tail",
+        "1",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert_eq!(output.trim(), "Client B");
+
+    // Errors instead of guesses
+    let failures: [&[&str]; 2] = [
+        &["reg", "--display", "amount"],
+        // Balances with more than one commodity have no single amount to sort by
+        &["bal", "--sort", "amount"],
+    ];
+    for (args, message) in failures.iter().zip(["not a boolean", "-X"].iter()) {
+        let assert = Command::cargo_bin("dinero")
+            .unwrap()
+            .args(*args)
+            .args(["--init-file", "tests/example_files/empty_ledgerrc"])
+            .args(["-f", "tests/example_files/multicurrency.ledger"])
+            .assert()
+            .failure();
+        let output = String::from_utf8(assert.get_output().to_owned().stderr).unwrap();
+        assert!(output.contains(message));
+    }
+}
+
+#[test]
+/// Posting status overrides the transaction status
+fn cleared_status() {
+    let args = &[
+        "cleared",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/status.ledger",
+        "Assets:Bank",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("950 EUR"));
+    assert!(output.contains("430 EUR"));
+
+    let args = &[
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/status.ledger",
+        "--flat",
+        "--pending",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("Expenses:Rent"));
+    assert!(!output.contains("Expenses:Food"));
+
+    let args = &[
+        "reg",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/status.ledger",
+        "--uncleared",
+        "--register-format",
+        "%(account) %(amount)",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert_eq!(output, "Expenses:Food 50 EUR\n");
+}
+
+#[test]
+/// Toggling the uncleared postings until the difference is zero marks them in the file
+fn reconcile() {
+    let journal = std::env::temp_dir().join("dinero_reconcile.ledger");
+    std::fs::copy("tests/example_files/status.ledger", &journal).unwrap();
+    let args = [
+        "reconcile",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        journal.to_str().unwrap(),
+        "Assets:Bank",
+        "--statement-balance",
+        "430 EUR",
+    ];
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .write_stdin("1\n2\ny\n")
+        .assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("2 postings marked as cleared"));
+
+    let args = [
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        journal.to_str().unwrap(),
+        "Assets:Bank",
+        "--uncleared",
+        "--pending",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.trim().is_empty());
+    std::fs::remove_file(journal).unwrap();
+}
+
+#[test]
+/// Net worth at the end of every month, converted with the prices at that date
+fn networth() {
+    let args = [
+        "networth",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/networth.ledger",
+        "-M",
+        "-X",
+        "EUR",
+        "--by-account",
+        "--bars",
+        "Assets",
+        "Liabilities",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("1,050 EUR"));
+    assert!(output.contains("-300 EUR"));
+    assert!(output.contains("2,300 EUR"));
+    assert!(output.contains("|#################### 750.00"));
+
+    // Without -X the balances are in several commodities and nothing is printed
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(&args[..6])
+        .assert()
+        .failure();
+    assert!(assert.get_output().stdout.is_empty());
+    let output = String::from_utf8(assert.get_output().to_owned().stderr).unwrap();
+    assert!(output.contains("-X"));
+}
+
+#[test]
+/// Periodic transactions are added after the last transaction, without checking their assertions
+fn forecast() {
+    let args = [
+        "reg",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/forecast.ledger",
+        "--forecast=from 2021-01-01 to 2021-06-01",
+        "--register-format",
+        "%(date) %(payee) %(total)",
+        "--date-format",
+        "%Y-%m-%d",
+        "Assets",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 8);
+    assert_eq!(lines[2], "2021-02-01 Salary 5,000 EUR");
+    assert_eq!(lines[7], "2021-05-15 Utilities 10,800 EUR");
+
+    // Without the option there is no forecast
+    let args = [
+        "reg",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/forecast.ledger",
+        "Assets",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert_eq!(output.lines().count(), 2);
+
+    // The balance marks the accounts with forecast postings
+    let args = [
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/forecast.ledger",
+        "--forecast=from 2021-01-01 to 2021-06-01",
+        "--flat",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("Assets:Bank *"));
+    assert!(output
+        .lines()
+        .any(|line| line.contains("Equity:Opening") & !line.contains('*')));
+    assert!(output.ends_with("* includes forecast postings\n"));
+}
+
+#[test]
+/// Returns of every fund and of the whole portfolio
+fn roi_by_account() {
+    let args = [
+        "roi",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/portfolio.ledger",
+        "--cash-flows",
+        "Bank",
+        "--assets-value",
+        "Investments",
+        "-X",
+        "EUR",
+        "--by-account",
+        "2",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let line = |account: &str| {
+        output
+            .lines()
+            .find(|x| x.contains(account))
+            .unwrap()
+            .to_string()
+    };
+    assert!(line("Investments:Bonds").contains("525 EUR"));
+    assert!(line("Investments:Bonds").contains("5.00%"));
+    assert!(line("Investments:Stocks").contains("-900 EUR"));
+    assert!(line("Total").contains("1,725 EUR"));
+
+    // A commodity bought for the first time has no price yet, its cost is its weight
+    let journal = "2021-01-01 * Buy
+    Investments:A    10 AAA @ 10 EUR
+    Assets:Bank
+2021-06-01 * Buy both
+    Investments:A    1 AAA @ 10 EUR
+    Investments:C    9 CCC @ 10 EUR
+    Assets:Bank
+P 2021-12-31 AAA 10 EUR
+P 2021-12-31 CCC 10 EUR
+";
+    let mut args = args;
+    args[4] = "-";
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .write_stdin(journal)
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let line = |account: &str| {
+        output
+            .lines()
+            .find(|x| x.contains(account))
+            .unwrap()
+            .to_string()
+    };
+    assert!(line("Investments:A").contains("-110 EUR"));
+    assert!(line("Investments:C").contains("-90 EUR"));
+    assert!(line("Investments:A").contains("0.00%"));
+
+    // The report by account has no calendar layout
+    Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .arg("--calendar")
+        .write_stdin(journal)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn roi_benchmark() {
+    let args = [
+        "roi",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/portfolio.ledger",
+        "--cash-flows",
+        "Bank",
+        "--assets-value",
+        "Investments",
+        "-X",
+        "EUR",
+        "-Q",
+        "--calendar",
+        "--benchmark",
+        "IDX",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let line = |title: &str| {
+        output
+            .lines()
+            .find(|x| x.contains(title))
+            .unwrap()
+            .to_string()
+    };
+    assert!(line("benchmark").contains("-4.00%"));
+    assert!(line("benchmark").contains("8.33%"));
+    assert!(line("excess").contains("-12.00%"));
+    assert!(line("Excess return:").contains("-12.00%"));
+
+    // Without a price at the start of the first quarter, the totals still have a benchmark
+    let journal = std::fs::read_to_string("tests/example_files/portfolio.ledger").unwrap();
+    let journal: Vec<&str> = journal.lines().filter(|x| !x.contains("IDX")).collect();
+    let journal = format!(
+        "{}\nP 2021-01-01 IDX 100 EUR\nP 2021-06-30 IDX 104 EUR\nP 2021-12-31 IDX 110 EUR\n",
+        journal.join("\n")
+    );
+    let mut args = args;
+    args[4] = "-";
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .write_stdin(journal)
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let line = |title: &str| {
+        output
+            .lines()
+            .find(|x| x.contains(title))
+            .unwrap()
+            .to_string()
+    };
+    assert!(line("benchmark").contains("n/a"));
+    assert!(line("benchmark").contains("4.00%"));
+    assert!(line("Benchmark (IDX):").contains("4.00%"));
+    assert!(line("Excess return:").contains("-14.00%"));
+}
+
+#[test]
+fn loan() {
+    let args = [
+        "loan",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/loan.ledger",
+        "--principal",
+        "1000 EUR",
+        "--rate",
+        "12",
+        "--term",
+        "12",
+        "--start",
+        "2021-02-01",
+        "--virtual",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 12 * 5);
+    assert!(lines[0].starts_with("2021-02-01 Loan payment"));
+    assert!(lines[1].contains("[Liabilities:Loan]") & lines[1].contains("78.85 EUR"));
+    assert!(lines[2].contains("[Expenses:Interest]") & lines[2].contains("10.00 EUR"));
+    assert!(lines[3].contains("-88.85 EUR"));
+    assert!(lines[55].starts_with("2022-01-01"));
+
+    // The precision of the principal or an explicit one
+    let mut args = args.to_vec();
+    args[6] = "1000.125 EUR";
+    let assert = Command::cargo_bin("dinero").unwrap().args(&args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.lines().nth(1).unwrap().contains(" 78.859 EUR"));
+    args.extend(["--precision", "0"]);
+    let assert = Command::cargo_bin("dinero").unwrap().args(&args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.lines().nth(1).unwrap().contains(" 79.00 EUR"));
+
+    // The schedule can be added to a journal that displays euros as 1.234,00 €
+    let args = [
+        "loan",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/demo.ledger",
+        "--principal",
+        "100000 EUR",
+        "--rate",
+        "3",
+        "--term",
+        "12",
+        "--start",
+        "2021-02-01",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let schedule = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    // The commodity has no decimals in the journal, the payments are rounded to cents
+    assert!(schedule.contains(" 8219.37 €"));
+    let journal = std::fs::read_to_string("tests/example_files/demo.ledger").unwrap();
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args([
+            "bal",
+            "--init-file",
+            "tests/example_files/empty_ledgerrc",
+            "-f",
+            "-",
+            "Liabilities:Loan",
+        ])
+        .write_stdin(format!("{}\n{}", journal, schedule))
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("100.000,00 €"));
+}
+
+#[test]
+fn depreciation() {
+    let args = [
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/depreciation.ledger",
+        "--flat",
+        "-e",
+        "2022-01-16",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let line = |account: &str| {
+        output
+            .lines()
+            .find(|x| x.contains(account))
+            .unwrap()
+            .to_string()
+    };
+    assert!(line("Assets:Equipment:Laptop").contains("800.00 EUR"));
+    assert!(line("Expenses:Depreciation").contains("400.00 EUR"));
+    assert!(line("Expenses:Depreciation:Printers").contains("100.00 EUR"));
+    assert!(!output.contains("Assets:Equipment:Printer"));
+}
+
+#[test]
+fn capital_gains() {
+    let args = [
+        "gains",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/gains.ledger",
+        "--average-cost",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(
+        lines[1],
+        "ACME,2020-03-01,2021-06-01,10,USD,1100.00,1500.00,400.00,long"
+    );
+    assert_eq!(
+        lines[2],
+        "ACME,2021-01-15,2021-06-01,5,USD,550.00,750.00,200.00,short"
+    );
+
+    // A sale without a cost takes the price implied by the transaction
+    let args = [
+        "gains",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "-",
+    ];
+    let purchase = "2021-01-01 Buy\n    Assets:Broker    10 AAA @ 10 EUR\n    Assets:Bank\n";
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .write_stdin(format!(
+            "{}2021-06-01 Sell\n    Assets:Broker    -5 AAA\n    Assets:Bank    75 EUR\n",
+            purchase
+        ))
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("AAA,2021-01-01,2021-06-01,5,EUR,50,75,25,short"));
+
+    // Unless there is no single price to imply
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .write_stdin(format!(
+            "{}2021-06-01 Sell\n    Assets:Broker    -5 AAA\n    Assets:Bank    40 EUR\n    Assets:Bank    40 USD\n",
+            purchase
+        ))
+        .assert()
+        .failure();
+    let output = String::from_utf8(assert.get_output().to_owned().stderr).unwrap();
+    assert!(output.contains("has no price"));
+}
+
+#[test]
+fn balance_several_currencies() {
+    let args = [
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/multicurrency.ledger",
+        "-X",
+        "EUR",
+        "-X",
+        "USD",
+        "--historical-cost",
+        "-e",
+        "2021-07-01",
+        "--flat",
+        "Assets",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let line = |account: &str| {
+        output
+            .lines()
+            .find(|x| x.contains(account))
+            .unwrap()
+            .to_string()
+    };
+    assert!(line("Cost").contains("EUR") & line("Cost").contains("USD"));
+    let broker = line("Assets:Broker");
+    assert!(broker.contains("510.00 EUR") & broker.contains("600.00 USD"));
+    assert!(broker.contains("500.00 USD"));
+    // A cost in more than one commodity has one line per commodity, so it fits in its column
+    let lines: Vec<&str> = output.lines().collect();
+    let n = lines.len();
+    assert!(lines[n - 2].ends_with("1,000.00 EUR"));
+    assert_eq!(lines[n - 1], format!("{:>60}", "1,000.00 USD"));
+}
+
+#[test]
+fn balance_percent() {
+    let args = [
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/multicurrency.ledger",
+        "-e",
+        "2021-07-01",
+        "-X",
+        "EUR",
+        "--percent",
+        "--share-of-total",
+        "Assets",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let line = |account: &str| {
+        output
+            .lines()
+            .find(|x| x.contains(account))
+            .unwrap()
+            .to_string()
+    };
+    assert!(line("1,000.00 EUR").contains("70.18%") & line("1,000.00 EUR").contains("51.68%"));
+    assert!(line("Broker").contains("26.36%"));
+
+    // Without -X there is more than one commodity
+    let args = [
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/multicurrency.ledger",
+        "--percent",
+        "Assets",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stderr).unwrap();
+    assert!(output.contains("more than one commodity"));
+}
+
+#[test]
+/// Running mean and deviation in the register, daily average in the balance
+fn averages() {
+    let args = [
+        "reg",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/demo.ledger",
+        "--average",
+        "--deviation",
+        "Expenses:Groceries",
+        "Expenses:Clothes",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let line = output.lines().find(|x| x.contains("Clothing")).unwrap();
+    assert!(line.contains("-USD15.32") & line.contains("USD84.69"));
+
+    let args = [
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/demo.ledger",
+        "-p",
+        "2021-01",
+        "--daily-average",
+        "Expenses:Groceries",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.lines().any(|x| x.contains("USD3.23")));
+}
+
+#[test]
+/// Month over month and year over year changes, and the unusual months
+fn trends() {
+    let args = [
+        "trends",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/trends.ledger",
+        "-b",
+        "2021-02-01",
+        "-e",
+        "2022-02-01",
+        "--depth",
+        "2",
+        "Expenses",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    let line = |account: &str| {
+        output
+            .lines()
+            .find(|x| x.contains(account))
+            .unwrap()
+            .to_string()
+    };
+    assert!(line("Expenses:Food").contains("500 EUR !"));
+    assert!(line("Expenses:Food").contains("20.00%"));
+    assert!(line("Expenses:Rent").contains("900 EUR !"));
+    assert!(line("Expenses:Rent").contains("12.50%"));
+
+    // A single month is compared with the year before it
+    let args = [
+        "trends",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/trends.ledger",
+        "-b",
+        "2022-01-01",
+        "Expenses:Rent",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("900 EUR !"));
+
+    let args = [
+        "trends",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/trends.ledger",
+        "-p",
+        "2021",
+        "--heatmap",
+        "Expenses:Rent",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output
+        .lines()
+        .any(|x| x.contains("Expenses:Rent") & x.contains("n/a")));
+}
@@ -87,3 +87,24 @@ fn display_currencies() {
     currency.set_format(&format_3);
     assert_eq!(format!("{}", money), "-€12,30");
 }
+
+#[test]
+fn one_line_commodity_format() {
+    let mut tokenizer: Tokenizer = Tokenizer::from(
+        "commodity 1.000,00 EUR
+commodity $1,000.0
+commodity VWCE2
+commodity \"S&P 500\"
+"
+        .to_string(),
+    );
+    let items = tokenizer.tokenize(&CommonOpts::from_iter(["", "-f", ""].iter()));
+    let eur = items.commodities.get("EUR").unwrap();
+    let money = Money::from((eur.clone(), BigRational::from_float(-1234.5).unwrap()));
+    assert_eq!(format!("{}", money), "-1.234,50 EUR");
+    let usd = items.commodities.get("$").unwrap();
+    let money = Money::from((usd.clone(), BigRational::from_float(1234.5).unwrap()));
+    assert_eq!(format!("{}", money), "$1,234.5");
+    assert!(items.commodities.get("VWCE2").is_ok());
+    assert!(items.commodities.get("S&P 500").is_ok());
+}
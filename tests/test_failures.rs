@@ -2,7 +2,6 @@ use dinero::{parser::Tokenizer, CommonOpts};
 use structopt::StructOpt;
 
 #[test]
-#[should_panic(expected = "Should be money.")]
 /// The expression in an automated account should evaluate to money
 fn not_money() {
     let mut tokenizer: Tokenizer = Tokenizer::from(
@@ -17,7 +16,24 @@ fn not_money() {
     );
     let parsed = tokenizer.tokenize(&CommonOpts::from_iter(["", "-f", ""].iter()));
 
-    // But to a wrong ledger -- panics!
-    let _ledger = parsed.to_ledger(&CommonOpts::from_iter(["", "-f", ""].iter()));
-    unreachable!("This has panicked")
+    // But to a wrong ledger
+    let ledger = parsed.to_ledger(&CommonOpts::from_iter(["", "-f", ""].iter()));
+    assert!(ledger.is_err());
+}
+
+#[test]
+/// Posting amounts that can't be computed are errors
+fn invalid_amount_expressions() {
+    for expression in ["(10 EUR + 5 USD)", "(10 EUR / 0)", "(amount * 2)"].iter() {
+        let mut tokenizer: Tokenizer = Tokenizer::from(format!(
+            "2021-01-15 * Flights
+    Expenses:Travel      {}
+    Assets:Checking account
+",
+            expression
+        ));
+        let options = CommonOpts::from_iter(["", "-f", ""].iter());
+        let parsed = tokenizer.tokenize(&options);
+        assert!(parsed.to_ledger(&options).is_err());
+    }
 }
@@ -1,9 +1,13 @@
+use assert_cmd::Command;
+use common::test_args;
 use dinero::parser::Tokenizer;
 use dinero::CommonOpts;
 
 use std::convert::TryFrom;
+use std::fs::read_to_string;
 use std::path::PathBuf;
 use structopt::StructOpt;
+mod common;
 
 #[test]
 fn test_include() {
@@ -74,3 +78,42 @@ fn comment_spaces() {
     let ledger = items.to_ledger(&options);
     assert!(ledger.is_ok());
 }
+
+#[test]
+fn several_input_files() {
+    let args = &[
+        "stats",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/demo.ledger",
+        "-f",
+        "tests/example_files/timeclock.ledger",
+    ];
+    let assert = Command::cargo_bin("dinero").unwrap().args(args).assert();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("Number of files processed: 2"));
+    test_args(args);
+}
+
+#[test]
+fn read_from_stdin() {
+    let args = &[
+        "bal",
+        "--init-file",
+        "tests/example_files/empty_ledgerrc",
+        "-f",
+        "tests/example_files/demo.ledger",
+        "-f",
+        "-",
+        "Project",
+    ];
+    let assert = Command::cargo_bin("dinero")
+        .unwrap()
+        .args(args)
+        .write_stdin(read_to_string("tests/example_files/timeclock.ledger").unwrap())
+        .assert()
+        .success();
+    let output = String::from_utf8(assert.get_output().to_owned().stdout).unwrap();
+    assert!(output.contains("12.75 h"));
+}